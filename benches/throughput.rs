@@ -0,0 +1,66 @@
+// Compares the instrumented `start` loop against `run_headless`, in instructions per second:
+// cargo bench --features bench
+//
+// criterion is not a dependency, so this is a plain `harness = false` binary timing each run with `Instant`
+
+use std::io;
+use std::time::{Duration, Instant};
+use nes::{Bus, Cpu, Result};
+
+const INSTRUCTIONS: u64 = 10_000_000;
+// best of, to keep a noisy run from setting the figure
+const SAMPLES: usize = 5;
+
+fn program() -> Result<Cpu> {
+    let mut bus = Bus::new();
+    bus.set_access_log_capacity(1024);
+    bus.write_u16(0xFFFC, 0x8000)?;
+    // LDX #$00; INX; STX $10; BNE -5; JMP $8000, the store keeps loop detection from stopping it early
+    bus.write_n(0x8000, &[0xA2, 0x00, 0xE8, 0x86, 0x10, 0xD0, 0xFB, 0x4C, 0x00, 0x80])?;
+    Cpu::new(bus)
+}
+
+// every optional diagnostic on, which `run_headless` turns off
+fn instrumented() -> Result<Cpu> {
+    let mut cpu = program()?;
+    cpu.set_rewind(16, 100_000);
+    cpu.attach_trace_writer(Box::new(io::sink()));
+    cpu.options_mut()
+        .set_hash_execution(true)
+        .set_detect_self_modifying_code(true)
+        .set_loop_detection(Some(1_000))
+        .set_instruction_limit(Some(INSTRUCTIONS));
+    Ok(cpu)
+}
+
+fn main() -> Result {
+    report("start", sample(|| {
+        let mut cpu = instrumented()?;
+        let started = Instant::now();
+        let summary = cpu.start();
+        assert_eq!(summary.instructions, INSTRUCTIONS, "stopped early: {:?}", summary.stop_reason);
+        Ok(started.elapsed())
+    })?);
+
+    report("run_headless", sample(|| {
+        let mut cpu = instrumented()?;
+        let started = Instant::now();
+        assert_eq!(cpu.run_headless(INSTRUCTIONS)?, INSTRUCTIONS);
+        Ok(started.elapsed())
+    })?);
+
+    Ok(())
+}
+
+fn sample(mut run: impl FnMut() -> Result<Duration>) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..SAMPLES {
+        best = best.min(run()?);
+    }
+
+    Ok(best)
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{:<12} {:>8.1?} {:>6.1}M instructions/s", name, elapsed, INSTRUCTIONS as f64 / elapsed.as_secs_f64() / 1e6);
+}
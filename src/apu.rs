@@ -0,0 +1,35 @@
+use std::ops::RangeInclusive;
+
+use crate::bus::MmioHandler;
+
+pub const ADDRESS_RANGE: RangeInclusive<u16> = 0x4000..=0x4017;
+// the only readable register, $4016 and $4017 read back the controllers
+const REGISTER_STATUS: u16 = 0x4015;
+
+// Stand-in until the APU is emulated, it only remembers what was written to each register
+#[derive(Default)]
+pub struct Apu {
+    registers: [Option<u8>; 0x18],
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, address: u16) -> Option<u8> {
+        self.registers.get(address.wrapping_sub(*ADDRESS_RANGE.start()) as usize).copied().flatten()
+    }
+}
+
+impl MmioHandler for Apu {
+    fn write(&mut self, address: u16, value: u8) {
+        if ADDRESS_RANGE.contains(&address) {
+            self.registers[(address - ADDRESS_RANGE.start()) as usize] = Some(value);
+        }
+    }
+
+    fn is_write_only(&self, address: u16) -> bool {
+        ADDRESS_RANGE.contains(&address) && address != REGISTER_STATUS
+    }
+}
@@ -0,0 +1,44 @@
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// Fixed-size ring buffer, once full the oldest access is overwritten
+pub(super) struct AccessLog {
+    accesses: Vec<Access>,
+    capacity: usize,
+    next: usize,
+}
+
+impl AccessLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { accesses: Vec::with_capacity(capacity), capacity, next: 0 }
+    }
+
+    pub fn push(&mut self, access: Access) {
+        if self.accesses.len() < self.capacity {
+            self.accesses.push(access);
+        } else {
+            self.accesses[self.next] = access;
+        }
+
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    // oldest first
+    pub fn to_vec(&self) -> Vec<Access> {
+        if self.accesses.len() < self.capacity {
+            self.accesses.clone()
+        } else {
+            [&self.accesses[self.next..], &self.accesses[..self.next]].concat()
+        }
+    }
+}
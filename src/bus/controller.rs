@@ -0,0 +1,77 @@
+use std::cell::Cell;
+
+use crate::types::BitRead;
+
+#[derive(Default)]
+pub struct Controller {
+    buttons: u8,
+    strobe: bool,
+    // reads shift this out, so it lives in a cell to keep bus reads `&self`
+    shift: Cell<u8>,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self, button: JoypadButton) {
+        self.buttons |= button.mask();
+    }
+
+    pub fn release(&mut self, button: JoypadButton) {
+        self.buttons &= !button.mask();
+    }
+
+    // one bit per button in `JoypadButton` order, A in bit 0
+    pub fn set_buttons(&mut self, state: u8) {
+        self.buttons = state;
+    }
+
+    pub fn is_pressed(&self, button: JoypadButton) -> bool {
+        self.buttons & button.mask() != 0
+    }
+
+    // the shift register reloads for as long as strobe is high, so the falling edge latches the buttons
+    pub fn write(&mut self, value: u8) {
+        if self.strobe || value.is_bit_set(0) {
+            self.shift.set(self.buttons);
+        }
+
+        self.strobe = value.is_bit_set(0);
+    }
+
+    // the bit `read` would return, without shifting
+    pub fn peek(&self) -> u8 {
+        if self.strobe { self.buttons & 0x01 } else { self.shift.get() & 0x01 }
+    }
+
+    // buttons come out in `JoypadButton` order, followed by ones once all eight have been read
+    pub fn read(&self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+
+        let shift = self.shift.get();
+        self.shift.set((shift >> 1) | 0x80);
+        shift & 0x01
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JoypadButton {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl JoypadButton {
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
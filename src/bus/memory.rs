@@ -0,0 +1,65 @@
+pub trait Memory {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+}
+
+pub struct Ram {
+    bytes: Box<[u8]>,
+}
+
+impl Ram {
+    const LENGTH: usize = u16::MAX as usize + 1;
+
+    pub fn new() -> Self {
+        Self { bytes: vec![0; Self::LENGTH].into_boxed_slice() }
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory for Ram {
+    fn read(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+}
+
+pub struct ClosureMemory<R, W>
+where
+    R: Fn(u16) -> u8,
+    W: FnMut(u16, u8),
+{
+    read: R,
+    write: W,
+}
+
+impl<R, W> ClosureMemory<R, W>
+where
+    R: Fn(u16) -> u8,
+    W: FnMut(u16, u8),
+{
+    pub fn new(read: R, write: W) -> Self {
+        Self { read, write }
+    }
+}
+
+impl<R, W> Memory for ClosureMemory<R, W>
+where
+    R: Fn(u16) -> u8,
+    W: FnMut(u16, u8),
+{
+    fn read(&self, address: u16) -> u8 {
+        (self.read)(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        (self.write)(address, value)
+    }
+}
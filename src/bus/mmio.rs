@@ -0,0 +1,27 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub type SharedMmioHandler = Rc<RefCell<dyn MmioHandler>>;
+
+// Notified after a write lands on the bus within the range the handler was registered for
+pub trait MmioHandler {
+    fn write(&mut self, address: u16, value: u8);
+
+    // a value here takes the place of memory for reads within the range
+    fn read(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    // `read` without its side effects, for debuggers and tracing; `None` falls back as an unanswered `read` does
+    fn peek(&self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    // reads that `read` leaves unanswered return open bus instead of memory
+    fn is_write_only(&self, _address: u16) -> bool {
+        false
+    }
+
+    // advanced by the CPU after it accounts for the cycles of each instruction or interrupt
+    fn tick(&mut self, _cycles: u8) {}
+}
@@ -0,0 +1,393 @@
+mod access;
+mod controller;
+mod memory;
+mod mmio;
+mod tests;
+
+pub use self::access::{Access, AccessKind};
+pub use self::controller::{Controller, JoypadButton};
+pub use self::memory::{Memory, Ram, ClosureMemory};
+pub use self::mmio::{MmioHandler, SharedMmioHandler};
+
+use std::cell::{Cell, RefCell};
+use std::ops::RangeInclusive;
+
+use self::access::AccessLog;
+use crate::cartridge::{Cartridge, Mapper, Mirroring, Nrom};
+use crate::types::Result;
+
+const ADDRESS_PRG_RAM: u16 = 0x6000;
+pub(crate) const ADDRESS_PRG: u16 = 0x8000;
+// writes strobe both ports, each is read back from its own address
+const ADDRESS_CONTROLLER: u16 = 0x4016;
+const ADDRESS_CONTROLLER_2: u16 = 0x4017;
+const ADDRESS_EXPANSION: RangeInclusive<u16> = 0x4020..=0x5FFF;
+
+pub struct Bus {
+    // TODO: replace with devices
+    memory: Box<dyn Memory>,
+    mapper: Option<Box<dyn Mapper>>,
+    controllers: [Controller; 2],
+    handlers: Vec<(RangeInclusive<u16>, SharedMmioHandler)>,
+    // reads are logged too, hence the cell
+    access_log: Option<RefCell<AccessLog>>,
+    // the log set aside while instrumentation is off, put back as it was
+    access_log_suspended: Option<RefCell<AccessLog>>,
+    // the last value driven onto the data bus, which unmapped reads return
+    open_bus: Cell<u8>,
+    expansion_open_bus: bool,
+    #[cfg(feature = "stats")]
+    read_count: Cell<u64>,
+    #[cfg(feature = "stats")]
+    write_count: Cell<u64>,
+    #[cfg(feature = "stats")]
+    counting: bool,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::with_memory(Box::new(Ram::new()))
+    }
+
+    pub fn with_memory(memory: Box<dyn Memory>) -> Self {
+        Self {
+            memory,
+            mapper: None,
+            controllers: [Controller::new(), Controller::new()],
+            handlers: vec![],
+            access_log: None,
+            access_log_suspended: None,
+            open_bus: Cell::new(0),
+            expansion_open_bus: false,
+            #[cfg(feature = "stats")]
+            read_count: Cell::new(0),
+            #[cfg(feature = "stats")]
+            write_count: Cell::new(0),
+            #[cfg(feature = "stats")]
+            counting: true,
+        }
+    }
+
+    // TODO: pick the mapper from the header once there is more than NROM
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.insert_mapper(Box::new(Nrom::new(cartridge)));
+    }
+
+    pub fn insert_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        self.mapper = Some(mapper);
+    }
+
+    pub fn has_mapper(&self) -> bool {
+        self.mapper.is_some()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.mapper.as_mut().and_then(|mapper| mapper.cartridge_mut())
+    }
+
+    pub fn register_handler(&mut self, range: RangeInclusive<u16>, handler: SharedMmioHandler) {
+        self.handlers.push((range, handler));
+    }
+
+    // a capacity of zero turns the log off
+    pub fn set_access_log_capacity(&mut self, capacity: usize) {
+        self.access_log = if capacity == 0 { None } else { Some(RefCell::new(AccessLog::new(capacity))) };
+    }
+
+    pub fn access_log(&self) -> Vec<Access> {
+        self.access_log.as_ref().map_or_else(Vec::new, |log| log.borrow().to_vec())
+    }
+
+    // turns the access log and the counters off and back on, leaving what they recorded so far untouched
+    pub(crate) fn set_instrumented(&mut self, enabled: bool) {
+        if enabled {
+            if let Some(log) = self.access_log_suspended.take() {
+                self.access_log = Some(log);
+            }
+        } else if let Some(log) = self.access_log.take() {
+            self.access_log_suspended = Some(log);
+        }
+
+        #[cfg(feature = "stats")]
+        {
+            self.counting = enabled;
+        }
+    }
+
+    // leaves $4020-$5FFF unmapped as on a bare NES, handlers registered there still see their accesses
+    pub fn set_expansion_open_bus(&mut self, enabled: bool) {
+        self.expansion_open_bus = enabled;
+    }
+
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus.get()
+    }
+
+    // every access since the bus was created, whichever device served it
+    #[cfg(feature = "stats")]
+    pub fn read_count(&self) -> u64 {
+        self.read_count.get()
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn write_count(&self) -> u64 {
+        self.write_count.get()
+    }
+
+    fn is_open_bus(&self, address: u16) -> bool {
+        self.expansion_open_bus && ADDRESS_EXPANSION.contains(&address)
+    }
+
+    // player 1
+    pub fn controller_mut(&mut self) -> &mut Controller {
+        &mut self.controllers[0]
+    }
+
+    // `player` is the zero-based port, `state` has one bit per button in `JoypadButton` order
+    pub fn set_buttons(&mut self, player: usize, state: u8) -> Result {
+        let controller = self.controllers.get_mut(player)
+            .ok_or_else(|| anyhow!("expected player `0` or `1`, received `{}`", player))?;
+        controller.set_buttons(state);
+        Ok(())
+    }
+
+    pub fn prg_ram(&self) -> &[u8] {
+        self.mapper.as_ref().map_or(&[], |mapper| mapper.prg_ram())
+    }
+
+    pub fn prg_ram_mut(&mut self) -> &mut [u8] {
+        match &mut self.mapper {
+            Some(mapper) => mapper.prg_ram_mut(),
+            None => &mut [],
+        }
+    }
+
+    pub fn mirroring(&self) -> Option<Mirroring> {
+        self.mapper.as_ref().map(|mapper| mapper.mirroring())
+    }
+
+    // the mapper owns the upper half of the address space and says which of it is ROM and which its registers
+    pub fn is_rom(&self, address: u16) -> bool {
+        address >= ADDRESS_PRG && self.mapper.as_ref().is_some_and(|mapper| mapper.is_rom(address))
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let value = match self.read_cartridge(address) {
+            Some(value) => value,
+            None => match self.read_handlers(address) {
+                Some(value) => value,
+                None if address == ADDRESS_CONTROLLER => self.controllers[0].read(),
+                None if address == ADDRESS_CONTROLLER_2 => self.controllers[1].read(),
+                None if self.is_open_bus(address) || self.is_write_only(address) => self.open_bus.get(),
+                None => self.memory.read(address),
+            },
+        };
+        self.open_bus.set(value);
+        #[cfg(feature = "stats")]
+        if self.counting {
+            self.read_count.set(self.read_count.get() + 1);
+        }
+
+        if let Some(log) = &self.access_log {
+            log.borrow_mut().push(Access { kind: AccessKind::Read, address, value });
+        }
+
+        value
+    }
+
+    // what `read` would return, without its side effects: devices answer through `MmioHandler::peek`, controllers
+    // are not shifted, and open bus, the access log and the counters are left alone
+    pub(crate) fn peek(&self, address: u16) -> u8 {
+        match self.read_cartridge(address) {
+            Some(value) => value,
+            None => match self.peek_handlers(address) {
+                Some(value) => value,
+                None if address == ADDRESS_CONTROLLER => self.controllers[0].peek(),
+                None if address == ADDRESS_CONTROLLER_2 => self.controllers[1].peek(),
+                None if self.is_open_bus(address) || self.is_write_only(address) => self.open_bus.get(),
+                None => self.memory.read(address),
+            },
+        }
+    }
+
+    // the mapper owns PRG ROM, and $6000-$7FFF when the cartridge has PRG-RAM
+    fn read_cartridge(&self, address: u16) -> Option<u8> {
+        let mapper = self.mapper.as_ref()?;
+        if address >= ADDRESS_PRG {
+            return Some(mapper.read(address));
+        }
+
+        let ram = mapper.prg_ram();
+        if address >= ADDRESS_PRG_RAM && !ram.is_empty() {
+            return Some(ram[(address - ADDRESS_PRG_RAM) as usize % ram.len()]);
+        }
+
+        None
+    }
+
+    // every address whose stored byte differs, with the value on this bus then on `other`; devices are left out
+    pub fn diff(&self, other: &Bus) -> Vec<(u16, u8, u8)> {
+        let stored = |bus: &Bus, address| bus.read_cartridge(address).unwrap_or_else(|| bus.memory.read(address));
+
+        (0..=u16::MAX)
+            .map(|address| (address, stored(self, address), stored(other, address)))
+            .filter(|(_, left, right)| left != right)
+            .collect()
+    }
+
+    fn peek_handlers(&self, address: u16) -> Option<u8> {
+        self.handlers.iter()
+            .filter(|(range, _)| range.contains(&address))
+            .find_map(|(_, handler)| handler.borrow().peek(address))
+    }
+
+    fn read_handlers(&self, address: u16) -> Option<u8> {
+        self.handlers.iter()
+            .filter(|(range, _)| range.contains(&address))
+            .find_map(|(_, handler)| handler.borrow_mut().read(address))
+    }
+
+    // reads here reach a device, whose answer can change without the CPU writing anything
+    pub(crate) fn is_device(&self, address: u16) -> bool {
+        address == ADDRESS_CONTROLLER
+            || address == ADDRESS_CONTROLLER_2
+            || self.handlers.iter().any(|(range, _)| range.contains(&address))
+    }
+
+    fn is_write_only(&self, address: u16) -> bool {
+        self.handlers.iter().any(|(range, handler)| range.contains(&address) && handler.borrow().is_write_only(address))
+    }
+
+    // a handler registered for several ranges is ticked once per range
+    pub fn tick(&mut self, cycles: u8) {
+        for (_, handler) in &self.handlers {
+            handler.borrow_mut().tick(cycles);
+        }
+    }
+
+    // for vectors and absolute operands, these never wrap so a word starting at $FFFF is an error
+    pub fn read_u16(&self, address: u16) -> Result<u16> {
+        if address.checked_add(1).is_some() {
+            let bytes = [self.read(address), self.read(address + 1)];
+            Ok(u16::from_le_bytes(bytes))
+        } else {
+            Err(anyhow!("address out of bounds"))
+        }
+    }
+
+    // the high byte comes from the same page, reproducing the 6502's indirect JMP bug
+    pub fn read_u16_within_page(&self, address: u16) -> u16 {
+        Self::read_u16_within_page_with(address, |address| self.read(address))
+    }
+
+    // for zero page pointers, the high byte of a pointer at $FF comes from $00 as on the 6502
+    pub fn read_zp_u16(&self, address: u8) -> u16 {
+        Self::read_zp_u16_with(address, |address| self.read(address))
+    }
+
+    // the same wrapping through any reader, so the CPU can peek or count its reads
+    pub(crate) fn read_u16_within_page_with(address: u16, read: impl Fn(u16) -> u8) -> u16 {
+        let address_high = (address & 0xFF00) | (address as u8).wrapping_add(1) as u16;
+        u16::from_le_bytes([read(address), read(address_high)])
+    }
+
+    pub(crate) fn read_zp_u16_with(address: u8, read: impl Fn(u16) -> u8) -> u16 {
+        u16::from_le_bytes([read(address as u16), read(address.wrapping_add(1) as u16)])
+    }
+
+    // like `fill`, the bytes may end at $FFFF but not run past it
+    pub fn read_n(&self, address: u16, n: u16) -> Result<Vec<u8>> {
+        if address as usize + n as usize <= 0x10000 {
+            let mut bytes = vec![];
+
+            for i in 0..n {
+                bytes.push(self.read(address + i));
+            }
+
+            Ok(bytes)
+        } else {
+            Err(anyhow!("address + n out of bounds"))
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(log) = &self.access_log {
+            log.borrow_mut().push(Access { kind: AccessKind::Write, address, value });
+        }
+        self.open_bus.set(value);
+        #[cfg(feature = "stats")]
+        if self.counting {
+            self.write_count.set(self.write_count.get() + 1);
+        }
+
+        if let Some(mapper) = self.mapper.as_mut().filter(|_| address >= ADDRESS_PRG) {
+            mapper.write(address, value);
+            return;
+        }
+
+        if address >= ADDRESS_PRG_RAM {
+            let ram = self.prg_ram_mut();
+            if !ram.is_empty() {
+                let len = ram.len();
+                ram[(address - ADDRESS_PRG_RAM) as usize % len] = value;
+                return;
+            }
+        }
+
+        if address == ADDRESS_CONTROLLER {
+            for controller in &mut self.controllers {
+                controller.write(value);
+            }
+        } else if !self.is_open_bus(address) {
+            self.memory.write(address, value);
+        }
+
+        for (range, handler) in &self.handlers {
+            if range.contains(&address) {
+                handler.borrow_mut().write(address, value);
+            }
+        }
+    }
+
+    pub fn write_u16(&mut self, address: u16, value: u16) -> Result {
+        if address.checked_add(1).is_some() {
+            let bytes = value.to_le_bytes();
+            self.write(address, bytes[0]);
+            self.write(address + 1, bytes[1]);
+            Ok(())
+        } else {
+            Err(anyhow!("address out of bounds"))
+        }
+    }
+
+    pub fn write_n(&mut self, address: u16, bytes: &[u8]) -> Result {
+        if address as usize + bytes.len() <= 0x10000 {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.write(address + i as u16, *byte);
+            }
+
+            Ok(())
+        } else {
+            Err(anyhow!("address + byte array length out of bounds"))
+        }
+    }
+
+    // the region may end at $FFFF but not run past it
+    pub fn fill(&mut self, start: u16, len: usize, value: u8) -> Result {
+        if start as usize + len > 0x10000 {
+            return Err(anyhow!("address + fill length out of bounds"));
+        }
+
+        for i in 0..len {
+            self.write(start + i as u16, value);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
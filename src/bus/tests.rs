@@ -0,0 +1,341 @@
+#![cfg(test)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::*;
+use crate::apu::{self, Apu};
+use crate::ppu::{self, PpuStub};
+use crate::cartridge::tests::image;
+
+#[test]
+fn mirroring() {
+    let mut bus = Bus::new();
+    assert_eq!(bus.mirroring(), None);
+
+    bus.insert_cartridge(Cartridge::from_bytes(&image(1, 1, 0b0000_0000, 0)).unwrap());
+    assert_eq!(bus.mirroring(), Some(Mirroring::Horizontal));
+
+    bus.insert_cartridge(Cartridge::from_bytes(&image(1, 1, 0b0000_0001, 0)).unwrap());
+    assert_eq!(bus.mirroring(), Some(Mirroring::Vertical));
+}
+
+#[test]
+fn read_write_prg() {
+    let mut bytes = image(1, 0, 0, 0);
+    bytes[16] = 0xEA;
+    bytes[16 + 0x3FFF] = 0x60;
+
+    let mut bus = Bus::new();
+    bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+    assert_eq!(bus.read(0x8000), 0xEA);
+    assert_eq!(bus.read(0xC000), 0xEA);
+    assert_eq!(bus.read(0xFFFF), 0x60);
+
+    bus.write(0x8000, 0x00);
+    assert_eq!(bus.read(0x8000), 0xEA);
+    assert!(bus.is_rom(0x8000));
+
+    bus.write(0x0200, 0x42);
+    assert_eq!(bus.read(0x0200), 0x42);
+}
+
+#[test]
+fn read_write_mapper() {
+    // answers every read with the last value written, wherever it went; registers at $8000-$9FFF
+    struct Latch(Rc<RefCell<Vec<(u16, u8)>>>);
+
+    impl Mapper for Latch {
+        fn read(&self, address: u16) -> u8 {
+            self.0.borrow().last().map_or(address as u8, |(_, value)| *value)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0.borrow_mut().push((address, value));
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+
+        fn is_rom(&self, address: u16) -> bool {
+            address >= 0xA000
+        }
+    }
+
+    let writes = Rc::new(RefCell::new(vec![]));
+    let mut bus = Bus::new();
+    bus.insert_mapper(Box::new(Latch(writes.clone())));
+    assert_eq!(bus.read(0x8001), 0x01);
+
+    bus.write(0x8000, 0x42);
+    bus.write(0x0200, 0x24);
+    assert_eq!(bus.read(0xC000), 0x42);
+    assert_eq!(bus.read(0x0200), 0x24);
+    assert_eq!(*writes.borrow(), vec![(0x8000, 0x42)]);
+    assert_eq!(bus.mirroring(), Some(Mirroring::Horizontal));
+    assert!(bus.cartridge_mut().is_none());
+    assert!(!bus.is_rom(0x8000));
+    assert!(!bus.is_rom(0x9FFF));
+    assert!(bus.is_rom(0xA000));
+    assert!(!bus.is_rom(0x6000));
+}
+
+#[test]
+fn read_prg_patched() {
+    let mut bytes = image(1, 0, 0, 0);
+    bytes[16 + 0x0010] = 0xA9;
+    bytes[16 + 0x0020] = 0xEA;
+
+    let mut bus = Bus::new();
+    bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+    let cartridge = bus.cartridge_mut().unwrap();
+    cartridge.apply_patch(0x8010, 0xAD, None);
+    cartridge.apply_patch(0x8020, 0x60, Some(0xEA));
+    cartridge.apply_patch(0xC020, 0x60, Some(0x00));
+
+    assert_eq!(bus.read(0x8010), 0xAD);
+    assert_eq!(bus.read(0xC010), 0xA9);
+    assert_eq!(bus.read(0x8020), 0x60);
+    assert_eq!(bus.read(0xC020), 0xEA);
+
+    bus.cartridge_mut().unwrap().clear_patches();
+    assert_eq!(bus.read(0x8010), 0xA9);
+}
+
+#[test]
+fn read_controller() {
+    let mut bus = Bus::new();
+    let controller = bus.controller_mut();
+    controller.press(JoypadButton::A);
+    controller.press(JoypadButton::Start);
+    controller.press(JoypadButton::Left);
+    controller.release(JoypadButton::Left);
+    assert!(controller.is_pressed(JoypadButton::Start));
+
+    bus.write(0x4016, 0x01);
+    assert_eq!(bus.read(0x4016), 0x01);
+    assert_eq!(bus.read(0x4016), 0x01);
+    bus.write(0x4016, 0x00);
+
+    let bits: Vec<u8> = (0..9).map(|_| bus.read(0x4016)).collect();
+    assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn read_controllers() {
+    let mut bus = Bus::new();
+    bus.set_buttons(0, 0b1000_0101).unwrap();
+    bus.set_buttons(1, 0b0101_0010).unwrap();
+    assert_eq!(bus.set_buttons(2, 0xFF).unwrap_err().to_string(), "expected player `0` or `1`, received `2`");
+    assert!(bus.controller_mut().is_pressed(JoypadButton::Select));
+
+    // one strobe latches both ports
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+
+    let player_1: Vec<u8> = (0..4).map(|_| bus.read(0x4016)).collect();
+    let player_2: Vec<u8> = (0..9).map(|_| bus.read(0x4017)).collect();
+    let player_1_rest: Vec<u8> = (0..5).map(|_| bus.read(0x4016)).collect();
+    assert_eq!(player_1, vec![1, 0, 1, 0]);
+    assert_eq!(player_1_rest, vec![0, 0, 0, 1, 1]);
+    assert_eq!(player_2, vec![0, 1, 0, 0, 1, 0, 1, 0, 1]);
+}
+
+#[test]
+fn read_write_only_handler() {
+    // $3000 only takes writes, $3001 reads back the last one
+    struct Register(Option<u8>);
+
+    impl MmioHandler for Register {
+        fn write(&mut self, _address: u16, value: u8) {
+            self.0 = Some(value);
+        }
+
+        fn read(&mut self, address: u16) -> Option<u8> {
+            if address == 0x3001 { self.0 } else { None }
+        }
+
+        fn is_write_only(&self, address: u16) -> bool {
+            address == 0x3000
+        }
+    }
+
+    let mut bus = Bus::new();
+    bus.register_handler(0x3000..=0x3001, Rc::new(RefCell::new(Register(None))));
+
+    bus.write(0x3000, 0x42);
+    bus.write(0x0200, 0x24);
+    assert_eq!(bus.read(0x3000), 0x24);
+    assert_eq!(bus.read(0x3001), 0x42);
+    assert_eq!(bus.read(0x3000), 0x42);
+
+    let ppu = Rc::new(RefCell::new(PpuStub::new(1)));
+    bus.register_handler(ppu::ADDRESS_RANGE, ppu);
+    bus.write(0x2000, 0x80);
+    bus.write(0x0200, 0x11);
+    assert_eq!(bus.read(0x2008), 0x11);
+}
+
+#[test]
+fn write_mmio_handler() {
+    let apu = Rc::new(RefCell::new(Apu::new()));
+    let mut bus = Bus::new();
+    bus.register_handler(apu::ADDRESS_RANGE, apu.clone());
+
+    bus.write(0x4015, 0x0F);
+    bus.write(0x4017, 0x40);
+    bus.write(0x4018, 0x01);
+    assert_eq!(apu.borrow().register(0x4015), Some(0x0F));
+    assert_eq!(apu.borrow().register(0x4017), Some(0x40));
+    assert_eq!(apu.borrow().register(0x4000), None);
+    assert_eq!(apu.borrow().register(0x4018), None);
+
+    bus.write(0x4015, 0x00);
+    assert_eq!(apu.borrow().register(0x4015), Some(0x00));
+}
+
+#[test]
+fn read_write_expansion_open_bus() {
+    let mut bus = Bus::new();
+    bus.write(0x5000, 0x42);
+    assert_eq!(bus.read(0x5000), 0x42);
+
+    bus.set_expansion_open_bus(true);
+    bus.write(0x5000, 0x24);
+    bus.write(0x0200, 0x99);
+    assert_eq!(bus.read(0x5000), 0x99);
+    assert_eq!(bus.read(0x0201), 0x00);
+    assert_eq!(bus.read(0x5FFF), 0x00);
+    assert_eq!(bus.open_bus(), 0x00);
+
+    bus.set_expansion_open_bus(false);
+    assert_eq!(bus.read(0x5000), 0x42);
+}
+
+#[test]
+fn read_n_zero() {
+    let mut bus = Bus::new();
+    bus.set_access_log_capacity(4);
+
+    assert_eq!(bus.read_n(0x0200, 0).unwrap(), vec![]);
+    assert_eq!(bus.read_n(0xFFFF, 0).unwrap(), vec![]);
+    assert!(bus.access_log().is_empty());
+}
+
+#[test]
+fn fill() {
+    let mut bus = Bus::new();
+    bus.fill(0x0300, 0x100, 0xAA).unwrap();
+
+    assert_eq!(bus.read(0x02FF), 0x00);
+    assert_eq!(bus.read(0x0300), 0xAA);
+    assert_eq!(bus.read(0x0380), 0xAA);
+    assert_eq!(bus.read(0x03FF), 0xAA);
+    assert_eq!(bus.read(0x0400), 0x00);
+
+    bus.fill(0xFFFE, 2, 0x55).unwrap();
+    assert_eq!(bus.read(0xFFFF), 0x55);
+    assert!(bus.fill(0xFFFF, 2, 0xAA).is_err());
+    assert_eq!(bus.read(0xFFFF), 0x55);
+}
+
+#[test]
+fn diff() {
+    let mut left = Bus::new();
+    let mut right = Bus::new();
+    left.write_n(0x0200, &[0x01, 0x02, 0x03]).unwrap();
+    right.write_n(0x0200, &[0x01, 0x22, 0x03]).unwrap();
+    right.write(0x07FF, 0x42);
+    left.write(0xFFFF, 0x10);
+    right.set_access_log_capacity(4);
+
+    assert_eq!(left.diff(&right), vec![(0x0201, 0x02, 0x22), (0x07FF, 0x00, 0x42), (0xFFFF, 0x10, 0x00)]);
+    assert_eq!(right.diff(&right), vec![]);
+    assert!(right.access_log().is_empty());
+}
+
+#[test]
+fn read_write_n_end_of_address_space() {
+    let mut bus = Bus::new();
+    bus.write_n(0xFFFE, &[0x01, 0x02]).unwrap();
+    assert_eq!(bus.read_n(0xFFFE, 2).unwrap(), vec![0x01, 0x02]);
+    assert_eq!(bus.read_n(0xFFFF, 1).unwrap(), vec![0x02]);
+
+    assert!(bus.read_n(0xFFFF, 2).is_err());
+    assert!(bus.write_n(0xFFFF, &[0x03, 0x04]).is_err());
+    assert_eq!(bus.read(0xFFFF), 0x02);
+}
+
+#[test]
+fn access_log_wrapping() {
+    let mut bus = Bus::new();
+    bus.write(0x0200, 0x01);
+    assert!(bus.access_log().is_empty());
+
+    bus.set_access_log_capacity(3);
+    bus.write(0x0200, 0x42);
+    bus.read(0x0200);
+    assert_eq!(bus.access_log(), vec![
+        Access { kind: AccessKind::Write, address: 0x0200, value: 0x42 },
+        Access { kind: AccessKind::Read, address: 0x0200, value: 0x42 },
+    ]);
+
+    bus.write(0x0300, 0x43);
+    bus.read(0x0300);
+    bus.read(0x0400);
+    assert_eq!(bus.access_log(), vec![
+        Access { kind: AccessKind::Write, address: 0x0300, value: 0x43 },
+        Access { kind: AccessKind::Read, address: 0x0300, value: 0x43 },
+        Access { kind: AccessKind::Read, address: 0x0400, value: 0x00 },
+    ]);
+
+    bus.set_access_log_capacity(0);
+    bus.read(0x0200);
+    assert!(bus.access_log().is_empty());
+}
+
+#[test]
+fn read_write_u16_little_endian() {
+    let mut bus = Bus::new();
+    bus.write_n(0x0200, &[0x34, 0x12]).unwrap();
+    assert_eq!(bus.read_u16(0x0200).unwrap(), 0x1234);
+    assert_eq!(bus.read_zp_u16(0x00), 0x0000);
+
+    bus.write_u16(0x0010, 0xBEEF).unwrap();
+    assert_eq!(bus.read(0x0010), 0xEF);
+    assert_eq!(bus.read(0x0011), 0xBE);
+    assert_eq!(bus.read_zp_u16(0x10), 0xBEEF);
+}
+
+#[test]
+fn read_u16_not_wrapping() {
+    let mut bus = Bus::new();
+    bus.write(0xFFFE, 0x34);
+    bus.write(0xFFFF, 0x12);
+    bus.write(0x0000, 0x56);
+
+    assert_eq!(bus.read_u16(0xFFFE).unwrap(), 0x1234);
+    assert!(bus.read_u16(0xFFFF).is_err());
+    assert_eq!(bus.read_zp_u16(0xFF), u16::from_le_bytes([bus.read(0x00FF), 0x56]));
+}
+
+#[test]
+fn read_zp_u16_wrapping() {
+    let mut bus = Bus::new();
+    bus.write(0x00FF, 0x34);
+    bus.write(0x0000, 0x12);
+    bus.write(0x0100, 0xFF);
+
+    assert_eq!(bus.read_zp_u16(0xFF), 0x1234);
+}
+
+#[test]
+fn read_u16_within_page() {
+    let mut bus = Bus::new();
+    bus.write(0x02FF, 0x34);
+    bus.write(0x0200, 0x12);
+    bus.write(0x0300, 0xFF);
+
+    assert_eq!(bus.read_u16_within_page(0x02FF), 0x1234);
+    assert_eq!(bus.read_u16_within_page(0x02FE), 0x3400);
+}
@@ -0,0 +1,13 @@
+// `CartridgeInfo` as JSON for front-ends, in the shape serde's derive would give it: one key per field and the
+// mirroring as its variant name; written by hand as serde cannot be a dependency yet
+
+use super::CartridgeInfo;
+
+impl CartridgeInfo {
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"mapper":{},"prg_banks":{},"chr_banks":{},"mirroring":"{:?}","has_battery":{},"is_nes2":{}}}"#,
+            self.mapper, self.prg_banks, self.chr_banks, self.mirroring, self.has_battery, self.is_nes2,
+        )
+    }
+}
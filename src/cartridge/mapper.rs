@@ -0,0 +1,63 @@
+use super::{Cartridge, Mirroring};
+use crate::bus::ADDRESS_PRG;
+
+// Owns everything from $8000 up; writes there reach the mapper instead of memory
+pub trait Mapper {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // whether a write here lands on ROM rather than a register, which NROM has none of
+    fn is_rom(&self, _address: u16) -> bool {
+        true
+    }
+
+    // mapped at $6000-$7FFF when not empty
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    // for mappers backed by an iNES image, so patches can be applied to it
+    fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        None
+    }
+}
+
+// mapper 0, fixed PRG with no registers to write
+pub struct Nrom {
+    cartridge: Cartridge,
+}
+
+impl Nrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self { cartridge }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read(&self, address: u16) -> u8 {
+        self.cartridge.read_prg(address.wrapping_sub(ADDRESS_PRG))
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.cartridge.mirroring()
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.cartridge.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.cartridge.prg_ram_mut()
+    }
+
+    fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        Some(&mut self.cartridge)
+    }
+}
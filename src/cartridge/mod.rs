@@ -0,0 +1,141 @@
+#[cfg(feature = "json")]
+mod json;
+mod mapper;
+pub(crate) mod tests;
+
+pub use self::mapper::{Mapper, Nrom};
+
+use crate::types::{Result, BitRead};
+use crate::bus::ADDRESS_PRG;
+
+const HEADER_LEN: usize = 16;
+const HEADER_MAGIC: [u8; 4] = *b"NES\x1A";
+const TRAINER_LEN: usize = 512;
+const PRG_BANK_LEN: usize = 0x4000;
+const CHR_BANK_LEN: usize = 0x2000;
+const PRG_RAM_LEN: usize = 0x2000;
+
+pub struct Cartridge {
+    mapper: u8,
+    mirroring: Mirroring,
+    has_battery: bool,
+    is_nes2: bool,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    patches: Vec<Patch>,
+}
+
+impl Cartridge {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || bytes[0..4] != HEADER_MAGIC {
+            return Err(anyhow!("missing iNES header"));
+        }
+
+        let prg_banks = bytes[4] as usize;
+        let chr_banks = bytes[5] as usize;
+        let flags_6 = bytes[6];
+        let flags_7 = bytes[7];
+
+        let mirroring = if flags_6.is_bit_set(3) {
+            Mirroring::FourScreen
+        } else if flags_6.is_bit_set(0) {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let prg_start = HEADER_LEN + if flags_6.is_bit_set(2) { TRAINER_LEN } else { 0 };
+        let chr_start = prg_start + prg_banks * PRG_BANK_LEN;
+        let chr_end = chr_start + chr_banks * CHR_BANK_LEN;
+
+        if bytes.len() < chr_end {
+            return Err(anyhow!("expected image to have length `{}`, received `{}`", chr_end, bytes.len()));
+        }
+
+        let has_battery = flags_6.is_bit_set(1);
+
+        Ok(Self {
+            mapper: (flags_7 & 0xF0) | (flags_6 >> 4),
+            mirroring,
+            has_battery,
+            is_nes2: flags_7 & 0b0000_1100 == 0b0000_1000,
+            prg_rom: bytes[prg_start..chr_start].to_vec(),
+            chr_rom: bytes[chr_start..chr_end].to_vec(),
+            // only battery-backed RAM is modelled, that is the part worth persisting
+            prg_ram: if has_battery { vec![0; PRG_RAM_LEN] } else { vec![] },
+            patches: vec![],
+        })
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    // NROM-style: a single 16 KiB bank is mirrored across the whole PRG window
+    pub fn read_prg(&self, offset: u16) -> u8 {
+        let value = if self.prg_rom.is_empty() {
+            0
+        } else {
+            self.prg_rom[offset as usize % self.prg_rom.len()]
+        };
+
+        let address = ADDRESS_PRG.wrapping_add(offset);
+        match self.patches.iter().find(|patch| patch.address == address) {
+            Some(patch) if patch.compare.map_or(true, |compare| compare == value) => patch.value,
+            _ => value,
+        }
+    }
+
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    pub fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    // Game Genie style: reads of `address` return `value`, but only while the ROM holds `compare` if one is given
+    pub fn apply_patch(&mut self, address: u16, value: u8, compare: Option<u8>) {
+        self.patches.retain(|patch| patch.address != address);
+        self.patches.push(Patch { address, value, compare });
+    }
+
+    pub fn clear_patches(&mut self) {
+        self.patches.clear();
+    }
+
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo {
+            mapper: self.mapper,
+            prg_banks: (self.prg_rom.len() / PRG_BANK_LEN) as u8,
+            chr_banks: (self.chr_rom.len() / CHR_BANK_LEN) as u8,
+            mirroring: self.mirroring,
+            has_battery: self.has_battery,
+            is_nes2: self.is_nes2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeInfo {
+    pub mapper: u8,
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub is_nes2: bool,
+}
+
+struct Patch {
+    address: u16,
+    value: u8,
+    compare: Option<u8>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
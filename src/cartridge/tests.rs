@@ -0,0 +1,79 @@
+#![cfg(test)]
+
+use super::*;
+
+pub fn image(prg_banks: u8, chr_banks: u8, flags_6: u8, flags_7: u8) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_LEN];
+    bytes[0..4].copy_from_slice(&HEADER_MAGIC);
+    bytes[4] = prg_banks;
+    bytes[5] = chr_banks;
+    bytes[6] = flags_6;
+    bytes[7] = flags_7;
+    bytes.resize(HEADER_LEN + prg_banks as usize * PRG_BANK_LEN + chr_banks as usize * CHR_BANK_LEN, 0);
+    bytes
+}
+
+#[test]
+fn from_bytes_info() {
+    let cartridge = Cartridge::from_bytes(&image(2, 1, 0b0001_0011, 0b0000_0000)).unwrap();
+    assert_eq!(cartridge.info(), CartridgeInfo {
+        mapper: 1,
+        prg_banks: 2,
+        chr_banks: 1,
+        mirroring: Mirroring::Vertical,
+        has_battery: true,
+        is_nes2: false,
+    });
+
+    let cartridge = Cartridge::from_bytes(&image(1, 0, 0b0100_1000, 0b0001_1000)).unwrap();
+    assert_eq!(cartridge.info(), CartridgeInfo {
+        mapper: 0x14,
+        prg_banks: 1,
+        chr_banks: 0,
+        mirroring: Mirroring::FourScreen,
+        has_battery: false,
+        is_nes2: true,
+    });
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn info_json() {
+    let info = Cartridge::from_bytes(&image(2, 1, 0b0001_0011, 0b0000_0000)).unwrap().info();
+    assert_eq!(
+        info.to_json(),
+        r#"{"mapper":1,"prg_banks":2,"chr_banks":1,"mirroring":"Vertical","has_battery":true,"is_nes2":false}"#,
+    );
+
+    let info = CartridgeInfo { mirroring: Mirroring::FourScreen, is_nes2: true, ..info };
+    assert_eq!(
+        info.to_json(),
+        r#"{"mapper":1,"prg_banks":2,"chr_banks":1,"mirroring":"FourScreen","has_battery":true,"is_nes2":true}"#,
+    );
+}
+
+#[test]
+fn from_bytes_invalid() {
+    assert!(Cartridge::from_bytes(&[0; HEADER_LEN]).is_err());
+
+    let mut bytes = image(1, 1, 0, 0);
+    bytes.truncate(HEADER_LEN + PRG_BANK_LEN);
+    assert!(Cartridge::from_bytes(&bytes).is_err());
+}
+
+#[test]
+fn nrom_read() {
+    let mut bytes = image(1, 0, 0b0000_0001, 0);
+    bytes[HEADER_LEN] = 0xEA;
+    bytes[HEADER_LEN + 0x3FFF] = 0x60;
+
+    let mut mapper: Box<dyn Mapper> = Box::new(Nrom::new(Cartridge::from_bytes(&bytes).unwrap()));
+    assert_eq!(mapper.read(0x8000), 0xEA);
+    assert_eq!(mapper.read(0xC000), 0xEA);
+    assert_eq!(mapper.read(0xFFFF), 0x60);
+    assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+    mapper.write(0x8000, 0x00);
+    assert_eq!(mapper.read(0x8000), 0xEA);
+    assert!(mapper.cartridge_mut().is_some());
+}
@@ -17,6 +17,14 @@ impl Clock {
         self.cycles += cycles as u64;
     }
 
+    pub fn reset(&mut self) {
+        self.cycles = 0;
+    }
+
+    pub fn restore(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
     fn determine_speed(mode: ClockMode) -> u32 {
         match mode {
             ClockMode::Ntsc => 1 / 1_789_773,
@@ -0,0 +1,52 @@
+use super::{Cpu, CpuStatus, SaveState};
+use crate::types::Result;
+
+// Steps two CPUs in lockstep, e.g. with and without a fix, comparing their state after every instruction
+pub struct DiffRunner {
+    left: Cpu,
+    right: Cpu,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDivergence {
+    // instructions completed by both sides before the states differed
+    pub instructions: u64,
+    pub left: SaveState,
+    pub right: SaveState,
+}
+
+impl DiffRunner {
+    // `right` takes on the registers and RAM of `left`, the buses are expected to hold the same program
+    pub fn new(left: Cpu, mut right: Cpu) -> Result<Self> {
+        right.load_state(&left.save_state())?;
+        right.status = left.status;
+        Ok(Self { left, right })
+    }
+
+    pub fn left(&self) -> &Cpu {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Cpu {
+        &self.right
+    }
+
+    // stops at the first divergence, after `instructions_max` instructions or once both sides halt
+    pub fn run(&mut self, instructions_max: u64) -> Result<Option<StateDivergence>> {
+        for instructions in 0..instructions_max {
+            if self.left.status == CpuStatus::Halted && self.right.status == CpuStatus::Halted {
+                break;
+            }
+
+            self.left.step()?;
+            self.right.step()?;
+
+            let (left, right) = (self.left.save_state(), self.right.save_state());
+            if left != right {
+                return Ok(Some(StateDivergence { instructions: instructions + 1, left, right }));
+            }
+        }
+
+        Ok(None)
+    }
+}
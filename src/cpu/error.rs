@@ -0,0 +1,73 @@
+use std::fmt;
+use super::instruction::{InstructionOperation, InstructionMode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuError {
+    // context over the error the instruction failed with, which `anyhow::Error::root_cause` reaches
+    ExecutionFailed {
+        pc: u16,
+        opcode: u8,
+        mode: InstructionMode,
+        operand: Vec<u8>,
+    },
+    InvalidInputMode {
+        operation: InstructionOperation,
+        mode: InstructionMode,
+    },
+    InvalidOperandLength {
+        expected: usize,
+        received: usize,
+    },
+    OperandOutOfRange {
+        index: usize,
+        len: usize,
+    },
+    PcDesynchronized {
+        operation: InstructionOperation,
+        address: u16,
+        pc: u16,
+    },
+    RestrictedOperation {
+        operation: InstructionOperation,
+    },
+    UnimplementedOperation {
+        operation: InstructionOperation,
+        reason: &'static str,
+    },
+    UnmappedResetVector {
+        address: u16,
+    },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::ExecutionFailed { pc, opcode, mode, operand } => {
+                write!(f, "opcode `${:02X}` ({:?}) at `${:04X}` with operand {:02X?} failed", opcode, mode, pc, operand)
+            },
+            CpuError::InvalidInputMode { operation, mode } => {
+                write!(f, "operation `{:?}` does not support input mode `{:?}`", operation, mode)
+            },
+            CpuError::InvalidOperandLength { expected, received } => {
+                write!(f, "expected operand to have length `{}`, received `{}`", expected, received)
+            },
+            CpuError::OperandOutOfRange { index, len } => {
+                write!(f, "operand byte `{}` is out of range for an operand of length `{}`", index, len)
+            },
+            CpuError::PcDesynchronized { operation, address, pc } => {
+                write!(f, "PC desynchronized after `{}` at `${:04X}`: moved to `${:04X}`", operation.mnemonic(), address, pc)
+            },
+            CpuError::RestrictedOperation { operation } => {
+                write!(f, "operation `{:?}` is not in the allowed set", operation)
+            },
+            CpuError::UnimplementedOperation { operation, reason } => {
+                write!(f, "unofficial operation `{}` {}", operation.mnemonic(), reason)
+            },
+            CpuError::UnmappedResetVector { address } => {
+                write!(f, "reset vector `${:04X}` is outside cartridge ROM", address)
+            },
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
@@ -0,0 +1,13 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuEvent {
+    SelfModifyingWrite {
+        pc: u16,
+        address: u16,
+        value: u8,
+    },
+    RomWrite {
+        pc: u16,
+        address: u16,
+        value: u8,
+    },
+}
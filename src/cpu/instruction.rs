@@ -1,30 +1,109 @@
-use crate::bus::Bus;
+use std::fmt;
+use super::CpuError;
 use crate::types::Result;
 
-#[derive(Debug, CopyGetters)]
+#[derive(Debug, Copy, Clone, PartialEq, CopyGetters)]
 #[getset(get_copy = "pub")]
+#[allow(clippy::len_without_is_empty)]
 pub struct Instruction {
+    opcode: u8,
     operation: InstructionOperation,
     mode: InstructionMode,
     len: u8,
     cycles_base: u8,
+    illegal: bool,
 }
 
 macro_rules! instruction {
-    ($operation:ident, $mode:ident, $cycles_base:literal) => {{
-        let mode = InstructionMode::$mode;
+    ($operation:ident, $mode:ident, $cycles_base:literal) => {
+        Instruction::new(InstructionOperation::$operation, InstructionMode::$mode, $cycles_base)
+    };
+}
 
-        Instruction {
-            operation: InstructionOperation::$operation,
-            mode,
-            len: mode.len_bytes(),
-            cycles_base: $cycles_base,
+// unofficial opcodes, these are marked so traces and feature gates can tell them apart
+macro_rules! illegal {
+    ($operation:ident, $mode:ident, $cycles_base:literal) => {
+        Instruction { illegal: true, ..Instruction::new(InstructionOperation::$operation, InstructionMode::$mode, $cycles_base) }
+    };
+}
+
+// evaluated once at compile time from `Instruction::table_entry`
+macro_rules! opcode_table {
+    ($default:expr, $field:ident) => {{
+        let mut table = [$default; 256];
+        let mut opcode = 0;
+        while opcode < 256 {
+            table[opcode] = Instruction::table_entry(opcode as u8).$field;
+            opcode += 1;
         }
+        table
     }};
 }
 
+pub(crate) const OPCODE_OP: [InstructionOperation; 256] = opcode_table!(InstructionOperation::Nop, operation);
+pub(crate) const OPCODE_MODE: [InstructionMode; 256] = opcode_table!(InstructionMode::Implied, mode);
+pub(crate) const OPCODE_LEN: [u8; 256] = opcode_table!(0, len);
+pub(crate) const OPCODE_CYCLES: [u8; 256] = opcode_table!(0, cycles_base);
+pub(crate) const OPCODE_ILLEGAL: [bool; 256] = opcode_table!(false, illegal);
+
+// linear decode without a bus, a trailing instruction missing operand bytes is dropped
+pub fn decode_stream(bytes: &[u8]) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+
+    while let Some(opcode) = bytes.get(offset) {
+        let instruction = Instruction::decode(*opcode);
+        offset += instruction.len() as usize;
+        if offset > bytes.len() {
+            break;
+        }
+
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
 impl Instruction {
-    pub fn from_opcode(opcode: u8) -> Instruction {
+    // built by hand rather than decoded, the opcode is left at $00
+    pub const fn new(operation: InstructionOperation, mode: InstructionMode, cycles_base: u8) -> Self {
+        Self {
+            opcode: 0,
+            operation,
+            mode,
+            len: mode.len_bytes(),
+            cycles_base,
+            illegal: false,
+        }
+    }
+
+    // stands in for a mis-sized table entry
+    #[cfg(test)]
+    pub(crate) fn with_len(self, len: u8) -> Self {
+        Self { len, ..self }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_opcode(self, opcode: u8) -> Self {
+        Self { opcode, ..self }
+    }
+
+    // the decode stage, between `Cpu::fetch` and `Cpu::execute`
+    pub fn decode(opcode: u8) -> Instruction {
+        let index = opcode as usize;
+
+        Self {
+            opcode,
+            operation: OPCODE_OP[index],
+            mode: OPCODE_MODE[index],
+            len: OPCODE_LEN[index],
+            cycles_base: OPCODE_CYCLES[index],
+            illegal: OPCODE_ILLEGAL[index],
+        }
+    }
+
+    // source of the opcode tables, every byte decodes to something and the match is checked for exhaustiveness
+    pub(crate) const fn table_entry(opcode: u8) -> Instruction {
         match opcode {
             0x69 => instruction!(Adc, Immediate,   2),
             0x65 => instruction!(Adc, ZeroPage,    3),
@@ -97,6 +176,8 @@ impl Instruction {
             0xE8 => instruction!(Inx, Implied,     2),
             0xC8 => instruction!(Iny, Implied,     2),
             0x4C => instruction!(Jmp, Absolute,    3),
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2
+                => illegal!(Jam, Implied,     2),
             0x6C => instruction!(Jmp, Indirect,    5),
             0x20 => instruction!(Jsr, Absolute,    6),
             0xA9 => instruction!(Lda, Immediate,   2),
@@ -177,20 +258,321 @@ impl Instruction {
             0x8A => instruction!(Txa, Implied,     2),
             0x9A => instruction!(Txs, Implied,     2),
             0x98 => instruction!(Tya, Implied,     2),
-            _ => unimplemented!("no instruction found for opcode `${:02X}`", opcode),
+            0xAB => illegal!(Lax, Immediate,   2),
+            0xA7 => illegal!(Lax, ZeroPage,    3),
+            0xB7 => illegal!(Lax, ZeroPageY,   4),
+            0xAF => illegal!(Lax, Absolute,    4),
+            0xBF => illegal!(Lax, AbsoluteY,   4),
+            0xA3 => illegal!(Lax, IndirectX,   6),
+            0xB3 => illegal!(Lax, IndirectY,   5),
+            0x87 => illegal!(Sax, ZeroPage,    3),
+            0x97 => illegal!(Sax, ZeroPageY,   4),
+            0x8F => illegal!(Sax, Absolute,    4),
+            0x83 => illegal!(Sax, IndirectX,   6),
+            0xEB => illegal!(Sbc, Immediate,   2),
+            0xC7 => illegal!(Dcp, ZeroPage,    5),
+            0xD7 => illegal!(Dcp, ZeroPageX,   6),
+            0xCF => illegal!(Dcp, Absolute,    6),
+            0xDF => illegal!(Dcp, AbsoluteX,   7),
+            0xDB => illegal!(Dcp, AbsoluteY,   7),
+            0xC3 => illegal!(Dcp, IndirectX,   8),
+            0xD3 => illegal!(Dcp, IndirectY,   8),
+            0xE7 => illegal!(Isb, ZeroPage,    5),
+            0xF7 => illegal!(Isb, ZeroPageX,   6),
+            0xEF => illegal!(Isb, Absolute,    6),
+            0xFF => illegal!(Isb, AbsoluteX,   7),
+            0xFB => illegal!(Isb, AbsoluteY,   7),
+            0xE3 => illegal!(Isb, IndirectX,   8),
+            0xF3 => illegal!(Isb, IndirectY,   8),
+            0x07 => illegal!(Slo, ZeroPage,    5),
+            0x17 => illegal!(Slo, ZeroPageX,   6),
+            0x0F => illegal!(Slo, Absolute,    6),
+            0x1F => illegal!(Slo, AbsoluteX,   7),
+            0x1B => illegal!(Slo, AbsoluteY,   7),
+            0x03 => illegal!(Slo, IndirectX,   8),
+            0x13 => illegal!(Slo, IndirectY,   8),
+            0x27 => illegal!(Rla, ZeroPage,    5),
+            0x37 => illegal!(Rla, ZeroPageX,   6),
+            0x2F => illegal!(Rla, Absolute,    6),
+            0x3F => illegal!(Rla, AbsoluteX,   7),
+            0x3B => illegal!(Rla, AbsoluteY,   7),
+            0x23 => illegal!(Rla, IndirectX,   8),
+            0x33 => illegal!(Rla, IndirectY,   8),
+            0x47 => illegal!(Sre, ZeroPage,    5),
+            0x57 => illegal!(Sre, ZeroPageX,   6),
+            0x4F => illegal!(Sre, Absolute,    6),
+            0x5F => illegal!(Sre, AbsoluteX,   7),
+            0x5B => illegal!(Sre, AbsoluteY,   7),
+            0x43 => illegal!(Sre, IndirectX,   8),
+            0x53 => illegal!(Sre, IndirectY,   8),
+            0x67 => illegal!(Rra, ZeroPage,    5),
+            0x77 => illegal!(Rra, ZeroPageX,   6),
+            0x6F => illegal!(Rra, Absolute,    6),
+            0x7F => illegal!(Rra, AbsoluteX,   7),
+            0x7B => illegal!(Rra, AbsoluteY,   7),
+            0x63 => illegal!(Rra, IndirectX,   8),
+            0x73 => illegal!(Rra, IndirectY,   8),
+            0x0B | 0x2B => illegal!(Anc, Immediate,   2),
+            0x4B => illegal!(Alr, Immediate,   2),
+            0x6B => illegal!(Arr, Immediate,   2),
+            0x8B => illegal!(Xaa, Immediate,   2),
+            0xCB => illegal!(Axs, Immediate,   2),
+            0x9F => illegal!(Ahx, AbsoluteY,   5),
+            0x93 => illegal!(Ahx, IndirectY,   6),
+            0x9C => illegal!(Shy, AbsoluteX,   5),
+            0x9E => illegal!(Shx, AbsoluteY,   5),
+            0x9B => illegal!(Tas, AbsoluteY,   5),
+            0xBB => illegal!(Las, AbsoluteY,   4),
+            0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA
+                => illegal!(Nop, Implied,     2),
+            0x80 | 0x82 | 0x89 | 0xC2 | 0xE2
+                => illegal!(Nop, Immediate,   2),
+            0x04 | 0x44 | 0x64
+                => illegal!(Nop, ZeroPage,    3),
+            0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4
+                => illegal!(Nop, ZeroPageX,   4),
+            0x0C => illegal!(Nop, Absolute,    4),
+            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC
+                => illegal!(Nop, AbsoluteX,   4),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl Instruction {
+    // `bytes` holds the full instruction, opcode included; `address` is where it is located
+    pub fn write_disassembly(&self, out: &mut impl fmt::Write, address: u16, bytes: &[u8]) -> fmt::Result {
+        let mnemonic = self.operation.mnemonic();
+        let operand_byte = || bytes.get(1).copied().unwrap_or(0);
+        let operand_word = || u16::from_le_bytes([operand_byte(), bytes.get(2).copied().unwrap_or(0)]);
+
+        match self.mode {
+            InstructionMode::Implied => write!(out, "{}", mnemonic),
+            InstructionMode::Accumulator => write!(out, "{} A", mnemonic),
+            InstructionMode::Immediate => write!(out, "{} #${:02X}", mnemonic, operand_byte()),
+            InstructionMode::Relative => {
+                let target = address
+                    .wrapping_add(self.len as u16)
+                    .wrapping_add(operand_byte() as i8 as u16);
+                write!(out, "{} ${:04X}", mnemonic, target)
+            },
+            InstructionMode::ZeroPage => write!(out, "{} ${:02X}", mnemonic, operand_byte()),
+            InstructionMode::ZeroPageX => write!(out, "{} ${:02X},X", mnemonic, operand_byte()),
+            InstructionMode::ZeroPageY => write!(out, "{} ${:02X},Y", mnemonic, operand_byte()),
+            InstructionMode::Absolute => write!(out, "{} ${:04X}", mnemonic, operand_word()),
+            InstructionMode::AbsoluteX => write!(out, "{} ${:04X},X", mnemonic, operand_word()),
+            InstructionMode::AbsoluteY => write!(out, "{} ${:04X},Y", mnemonic, operand_word()),
+            InstructionMode::Indirect => write!(out, "{} (${:04X})", mnemonic, operand_word()),
+            InstructionMode::IndirectX => write!(out, "{} (${:02X},X)", mnemonic, operand_byte()),
+            InstructionMode::IndirectY => write!(out, "{} (${:02X}),Y", mnemonic, operand_byte()),
+        }
+    }
+
+    // where a branch, JMP or JSR at `address` transfers control to; indirect jumps are not followed
+    pub fn jump_target(&self, address: u16, bytes: &[u8]) -> Option<u16> {
+        match self.decode_operand(bytes) {
+            Operand::Relative(offset) => Some(address.wrapping_add(self.len as u16).wrapping_add(offset as u16)),
+            Operand::Absolute(target) if matches!(self.operation, InstructionOperation::Jmp | InstructionOperation::Jsr) => {
+                Some(target)
+            },
+            _ => None,
+        }
+    }
+
+    // `bytes` holds the full instruction, opcode included
+    pub fn decode_operand(&self, bytes: &[u8]) -> Operand {
+        let operand_byte = bytes.get(1).copied().unwrap_or(0);
+        let operand_word = u16::from_le_bytes([operand_byte, bytes.get(2).copied().unwrap_or(0)]);
+
+        match self.mode {
+            InstructionMode::Implied => Operand::None,
+            InstructionMode::Accumulator => Operand::Accumulator,
+            InstructionMode::Immediate => Operand::Immediate(operand_byte),
+            InstructionMode::Relative => Operand::Relative(operand_byte as i8),
+            InstructionMode::ZeroPage => Operand::ZeroPage(operand_byte),
+            InstructionMode::ZeroPageX => Operand::ZeroPageX(operand_byte),
+            InstructionMode::ZeroPageY => Operand::ZeroPageY(operand_byte),
+            InstructionMode::Absolute => Operand::Absolute(operand_word),
+            InstructionMode::AbsoluteX => Operand::AbsoluteX(operand_word),
+            InstructionMode::AbsoluteY => Operand::AbsoluteY(operand_word),
+            InstructionMode::Indirect => Operand::Indirect(operand_word),
+            InstructionMode::IndirectX => Operand::IndirectX(operand_byte),
+            InstructionMode::IndirectY => Operand::IndirectY(operand_byte),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum InstructionOperation {
     Adc, And, Asl, Bcc, Bcs, Beq, Bit, Bmi, Bne, Bpl, Brk, Bvc, Bvs, Clc,
-    Cld, Cli, Clv, Cmp, Cpx, Cpy, Dec, Dex, Dey, Eor, Inc, Inx, Iny, Jmp,
-    Jsr, Lda, Ldx, Ldy, Lsr, Nop, Ora, Pha, Php, Pla, Plp, Rol, Ror, Rti,
-    Rts, Sbc, Sec, Sed, Sei, Sta, Stx, Sty, Tax, Tay, Tsx, Txa, Txs, Tya,
+    Cld, Cli, Clv, Cmp, Cpx, Cpy, Dec, Dex, Dey, Eor, Inc, Inx, Iny, Jam,
+    Jmp, Jsr, Lda, Ldx, Ldy, Lsr, Nop, Ora, Pha, Php, Pla, Plp, Rol, Ror,
+    Rti, Rts, Sbc, Sec, Sed, Sei, Sta, Stx, Sty, Tax, Tay, Tsx, Txa, Txs,
+    Tya,
+    // unofficial
+    Ahx, Alr, Anc, Arr, Axs, Dcp, Isb, Las, Lax, Rla, Rra, Sax, Shx, Shy,
+    Slo, Sre, Tas, Xaa,
 }
 
-#[derive(Debug, Copy, Clone)]
+impl InstructionOperation {
+    // declaration order, official operations first
+    pub const ALL: &'static [InstructionOperation] = &[
+        InstructionOperation::Adc, InstructionOperation::And, InstructionOperation::Asl, InstructionOperation::Bcc,
+        InstructionOperation::Bcs, InstructionOperation::Beq, InstructionOperation::Bit, InstructionOperation::Bmi,
+        InstructionOperation::Bne, InstructionOperation::Bpl, InstructionOperation::Brk, InstructionOperation::Bvc,
+        InstructionOperation::Bvs, InstructionOperation::Clc, InstructionOperation::Cld, InstructionOperation::Cli,
+        InstructionOperation::Clv, InstructionOperation::Cmp, InstructionOperation::Cpx, InstructionOperation::Cpy,
+        InstructionOperation::Dec, InstructionOperation::Dex, InstructionOperation::Dey, InstructionOperation::Eor,
+        InstructionOperation::Inc, InstructionOperation::Inx, InstructionOperation::Iny, InstructionOperation::Jam,
+        InstructionOperation::Jmp, InstructionOperation::Jsr, InstructionOperation::Lda, InstructionOperation::Ldx,
+        InstructionOperation::Ldy, InstructionOperation::Lsr, InstructionOperation::Nop, InstructionOperation::Ora,
+        InstructionOperation::Pha, InstructionOperation::Php, InstructionOperation::Pla, InstructionOperation::Plp,
+        InstructionOperation::Rol, InstructionOperation::Ror, InstructionOperation::Rti, InstructionOperation::Rts,
+        InstructionOperation::Sbc, InstructionOperation::Sec, InstructionOperation::Sed, InstructionOperation::Sei,
+        InstructionOperation::Sta, InstructionOperation::Stx, InstructionOperation::Sty, InstructionOperation::Tax,
+        InstructionOperation::Tay, InstructionOperation::Tsx, InstructionOperation::Txa, InstructionOperation::Txs,
+        InstructionOperation::Tya,
+        InstructionOperation::Ahx, InstructionOperation::Alr, InstructionOperation::Anc, InstructionOperation::Arr,
+        InstructionOperation::Axs, InstructionOperation::Dcp, InstructionOperation::Isb, InstructionOperation::Las,
+        InstructionOperation::Lax, InstructionOperation::Rla, InstructionOperation::Rra, InstructionOperation::Sax,
+        InstructionOperation::Shx, InstructionOperation::Shy, InstructionOperation::Slo, InstructionOperation::Sre,
+        InstructionOperation::Tas, InstructionOperation::Xaa,
+    ];
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            InstructionOperation::Adc => "ADC",
+            InstructionOperation::And => "AND",
+            InstructionOperation::Asl => "ASL",
+            InstructionOperation::Bcc => "BCC",
+            InstructionOperation::Bcs => "BCS",
+            InstructionOperation::Beq => "BEQ",
+            InstructionOperation::Bit => "BIT",
+            InstructionOperation::Bmi => "BMI",
+            InstructionOperation::Bne => "BNE",
+            InstructionOperation::Bpl => "BPL",
+            InstructionOperation::Brk => "BRK",
+            InstructionOperation::Bvc => "BVC",
+            InstructionOperation::Bvs => "BVS",
+            InstructionOperation::Clc => "CLC",
+            InstructionOperation::Cld => "CLD",
+            InstructionOperation::Cli => "CLI",
+            InstructionOperation::Clv => "CLV",
+            InstructionOperation::Cmp => "CMP",
+            InstructionOperation::Cpx => "CPX",
+            InstructionOperation::Cpy => "CPY",
+            InstructionOperation::Dec => "DEC",
+            InstructionOperation::Dex => "DEX",
+            InstructionOperation::Dey => "DEY",
+            InstructionOperation::Eor => "EOR",
+            InstructionOperation::Inc => "INC",
+            InstructionOperation::Inx => "INX",
+            InstructionOperation::Iny => "INY",
+            InstructionOperation::Jam => "JAM",
+            InstructionOperation::Jmp => "JMP",
+            InstructionOperation::Jsr => "JSR",
+            InstructionOperation::Lda => "LDA",
+            InstructionOperation::Ldx => "LDX",
+            InstructionOperation::Ldy => "LDY",
+            InstructionOperation::Lsr => "LSR",
+            InstructionOperation::Nop => "NOP",
+            InstructionOperation::Ora => "ORA",
+            InstructionOperation::Pha => "PHA",
+            InstructionOperation::Php => "PHP",
+            InstructionOperation::Pla => "PLA",
+            InstructionOperation::Plp => "PLP",
+            InstructionOperation::Rol => "ROL",
+            InstructionOperation::Ror => "ROR",
+            InstructionOperation::Rti => "RTI",
+            InstructionOperation::Rts => "RTS",
+            InstructionOperation::Sbc => "SBC",
+            InstructionOperation::Sec => "SEC",
+            InstructionOperation::Sed => "SED",
+            InstructionOperation::Sei => "SEI",
+            InstructionOperation::Sta => "STA",
+            InstructionOperation::Stx => "STX",
+            InstructionOperation::Sty => "STY",
+            InstructionOperation::Tax => "TAX",
+            InstructionOperation::Tay => "TAY",
+            InstructionOperation::Tsx => "TSX",
+            InstructionOperation::Txa => "TXA",
+            InstructionOperation::Txs => "TXS",
+            InstructionOperation::Tya => "TYA",
+            InstructionOperation::Ahx => "AHX",
+            InstructionOperation::Alr => "ALR",
+            InstructionOperation::Anc => "ANC",
+            InstructionOperation::Arr => "ARR",
+            InstructionOperation::Axs => "AXS",
+            InstructionOperation::Dcp => "DCP",
+            InstructionOperation::Isb => "ISB",
+            InstructionOperation::Las => "LAS",
+            InstructionOperation::Lax => "LAX",
+            InstructionOperation::Rla => "RLA",
+            InstructionOperation::Rra => "RRA",
+            InstructionOperation::Sax => "SAX",
+            InstructionOperation::Shx => "SHX",
+            InstructionOperation::Shy => "SHY",
+            InstructionOperation::Slo => "SLO",
+            InstructionOperation::Sre => "SRE",
+            InstructionOperation::Tas => "TAS",
+            InstructionOperation::Xaa => "XAA",
+        }
+    }
+
+    pub fn is_store(&self) -> bool {
+        matches!(
+            self,
+            InstructionOperation::Sta
+                | InstructionOperation::Stx
+                | InstructionOperation::Sty
+                | InstructionOperation::Sax
+                | InstructionOperation::Ahx
+                | InstructionOperation::Shx
+                | InstructionOperation::Shy
+                | InstructionOperation::Tas
+        )
+    }
+
+    // operations that set PC themselves, rather than only stepping over their own bytes
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            InstructionOperation::Bcc
+                | InstructionOperation::Bcs
+                | InstructionOperation::Beq
+                | InstructionOperation::Bmi
+                | InstructionOperation::Bne
+                | InstructionOperation::Bpl
+                | InstructionOperation::Brk
+                | InstructionOperation::Bvc
+                | InstructionOperation::Bvs
+                | InstructionOperation::Jam
+                | InstructionOperation::Jmp
+                | InstructionOperation::Jsr
+                | InstructionOperation::Rti
+                | InstructionOperation::Rts
+        )
+    }
+
+    pub fn is_read_modify_write(&self) -> bool {
+        matches!(
+            self,
+            InstructionOperation::Asl
+                | InstructionOperation::Lsr
+                | InstructionOperation::Rol
+                | InstructionOperation::Ror
+                | InstructionOperation::Inc
+                | InstructionOperation::Dec
+                | InstructionOperation::Slo
+                | InstructionOperation::Rla
+                | InstructionOperation::Sre
+                | InstructionOperation::Rra
+                | InstructionOperation::Dcp
+                | InstructionOperation::Isb
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InstructionMode {
     Implied,
     Accumulator,
@@ -208,9 +590,14 @@ pub enum InstructionMode {
 }
 
 impl InstructionMode {
-    pub fn len_bytes(&self) -> u8 {
+    // the opcode plus its operand
+    pub const fn len_bytes(&self) -> u8 {
+        1 + self.operand_len()
+    }
+
+    pub const fn operand_len(&self) -> u8 {
         match self {
-            InstructionMode::Implied | InstructionMode::Accumulator => 1,
+            InstructionMode::Implied | InstructionMode::Accumulator => 0,
             InstructionMode::Immediate
                 | InstructionMode::Relative
                 | InstructionMode::ZeroPage
@@ -218,16 +605,33 @@ impl InstructionMode {
                 | InstructionMode::ZeroPageY
                 | InstructionMode::IndirectX
                 | InstructionMode::IndirectY
-                => 2,
+                => 1,
             InstructionMode::Absolute
                 | InstructionMode::AbsoluteX
                 | InstructionMode::AbsoluteY
                 | InstructionMode::Indirect
-                => 3,
+                => 2,
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    None,
+    Accumulator,
+    Immediate(u8),
+    Relative(i8),
+    ZeroPage(u8),
+    ZeroPageX(u8),
+    ZeroPageY(u8),
+    Absolute(u16),
+    AbsoluteX(u16),
+    AbsoluteY(u16),
+    Indirect(u16),
+    IndirectX(u8),
+    IndirectY(u8),
+}
+
 #[derive(Debug, PartialEq)]
 pub enum InstructionInput {
     Implied,
@@ -235,12 +639,25 @@ pub enum InstructionInput {
     Location(InstructionInputLocation),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InstructionInputLocation {
     Accumulator,
     Address(u16),
 }
 
+impl InstructionInputLocation {
+    pub fn as_address(&self) -> Option<u16> {
+        match self {
+            InstructionInputLocation::Address(address) => Some(*address),
+            InstructionInputLocation::Accumulator => None,
+        }
+    }
+
+    pub fn is_accumulator(&self) -> bool {
+        *self == InstructionInputLocation::Accumulator
+    }
+}
+
 impl InstructionInput {
     pub fn from_address(address: u16) -> Self {
         Self::Location(InstructionInputLocation::Address(address))
@@ -260,3 +677,45 @@ impl InstructionInput {
         }
     }
 }
+
+// the bytes following the opcode, at most two
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OperandBytes {
+    bytes: [u8; 2],
+    len: u8,
+}
+
+impl OperandBytes {
+    pub fn new(operand: &[u8]) -> Result<Self> {
+        if operand.len() > 2 {
+            return Err(CpuError::InvalidOperandLength { expected: 2, received: operand.len() }.into());
+        }
+
+        let mut bytes = [0; 2];
+        bytes[..operand.len()].copy_from_slice(operand);
+        Ok(Self { bytes, len: operand.len() as u8 })
+    }
+
+    pub(super) fn from_array(bytes: [u8; 2], len: u8) -> Self {
+        debug_assert!(len <= 2);
+        Self { bytes, len }
+    }
+
+    // `bytes` holds the full instruction, opcode included
+    pub fn from_instruction(bytes: &[u8]) -> Result<Self> {
+        Self::new(bytes.get(1..).unwrap_or(&[]))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn get(&self, index: usize) -> Result<u8> {
+        self.as_slice().get(index).copied()
+            .ok_or_else(|| CpuError::OperandOutOfRange { index, len: self.len as usize }.into())
+    }
+
+    pub fn word(&self) -> Result<u16> {
+        Ok(u16::from_le_bytes([self.get(0)?, self.get(1)?]))
+    }
+}
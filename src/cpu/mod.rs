@@ -1,36 +1,100 @@
 mod clock;
+mod diff;
+mod error;
+mod event;
 mod instruction;
+mod options;
+mod rewind;
+mod state;
+mod summary;
 mod tests;
-
+mod trace;
+
+pub use self::diff::{DiffRunner, StateDivergence};
+pub use self::error::CpuError;
+pub use self::event::CpuEvent;
+pub use self::options::{CpuOptions, CpuVariant};
+pub use self::state::SaveState;
+pub use self::summary::{RunSummary, StopReason};
+pub use self::trace::{TraceLine, TraceField, TraceDivergence};
+pub use self::instruction::{decode_stream, Instruction, OperandBytes, InstructionOperation, InstructionMode, InstructionInputLocation, Operand};
+
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
 use self::clock::{Clock, ClockMode};
-use self::instruction::{
-    Instruction,
-    InstructionOperation,
-    InstructionMode,
-    InstructionInput,
-    InstructionInputLocation,
-};
-use crate::bus::Bus;
+use self::instruction::{InstructionInput, OPCODE_CYCLES};
+use self::rewind::RewindBuffer;
+use self::trace::TraceWriter;
+use crate::bus::{Bus, ADDRESS_PRG};
 use crate::types::{Result, BitRead};
 
 const ADDRESS_VECTOR_NMI: u16 = 0xFFFA;
 const ADDRESS_VECTOR_RESET: u16 = 0xFFFC;
 const ADDRESS_VECTOR_IRQ: u16 = 0xFFFE;
+const ADDRESS_CYCLE_TIMER: RangeInclusive<u16> = 0x4020..=0x4023;
+const CYCLES_INTERRUPT: u8 = 7;
+// where `call` returns to, the stack pointer tells it apart from the subroutine jumping there itself
+const ADDRESS_CALL_RETURN: u16 = 0x0000;
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+// events kept until taken, past this the oldest are dropped
+const EVENTS_CAPACITY_DEFAULT: usize = 1024;
 
 pub struct Cpu {
     bus: Bus,
     registers: RegisterSet,
     vectors: VectorSet,
     clock: Clock,
+    options: CpuOptions,
+    events: Vec<CpuEvent>,
+    events_capacity: usize,
+    address_fetch_next: u16,
+    // the instruction being executed, for events and error reports
+    instruction_address: u16,
+    operations_allowed: Option<HashSet<InstructionOperation>>,
+    ticks_elapsed: u8,
+    ticks_remaining: u8,
+    status: CpuStatus,
+    execution_hash: u64,
+    interrupt_cycles: u64,
+    nmi_pending: bool,
+    irq_pending: bool,
+    irq_line: bool,
+    nmi_line: bool,
+    // absolute cycle counts at which an interrupt is raised
+    interrupts_scheduled: Vec<(u64, Interrupt)>,
+    breakpoints: HashSet<u16>,
+    missing_opcodes: BTreeSet<u8>,
+    rewind: Option<RewindBuffer>,
+    // registers on landing at each jump or branch target, and how many times in a row they were the same
+    loop_landings: HashMap<u16, (RegisterSet, u32)>,
+    loop_detected: Option<u16>,
+    // a device was read since the last landing, so what looks like a loop may be polling it
+    loop_device_read: Cell<bool>,
+    // base cycles by opcode for clones with their own timing, in place of the instruction table
+    cycle_table: Option<Box<[u8; 256]>>,
+    trace_writer: Option<TraceWriter>,
 }
 
 impl Cpu {
     pub fn new(bus: Bus) -> Result<Self> {
-        let vectors = VectorSet {
-            nmi: bus.read_u16(ADDRESS_VECTOR_NMI)?,
-            reset: bus.read_u16(ADDRESS_VECTOR_RESET)?,
-            irq: bus.read_u16(ADDRESS_VECTOR_IRQ)?,
-        };
+        Self::with_options(bus, CpuOptions::default())
+    }
+
+    // a reset vector outside cartridge space is a warning, or an error under `strict_rom`
+    pub fn with_options(bus: Bus, options: CpuOptions) -> Result<Self> {
+        let vectors = VectorSet::read(&bus)?;
+
+        if bus.has_mapper() && vectors.reset < ADDRESS_PRG {
+            if options.strict_rom() {
+                return Err(CpuError::UnmappedResetVector { address: vectors.reset }.into());
+            }
+
+            warn!("reset vector ${:04X} is outside cartridge ROM", vectors.reset);
+        }
 
         let mut registers = RegisterSet::new();
         registers.pc = vectors.reset;
@@ -38,58 +102,998 @@ impl Cpu {
         // TODO: hard-coded
         let clock = Clock::new(ClockMode::Ntsc);
 
-        Ok(Self { bus, registers, vectors, clock })
+        Ok(Self {
+            bus,
+            registers,
+            vectors,
+            clock,
+            options,
+            events: vec![],
+            events_capacity: EVENTS_CAPACITY_DEFAULT,
+            address_fetch_next: 0,
+            instruction_address: 0,
+            operations_allowed: None,
+            ticks_elapsed: 0,
+            ticks_remaining: 0,
+            status: CpuStatus::Running,
+            execution_hash: FNV_OFFSET_BASIS,
+            interrupt_cycles: 0,
+            nmi_pending: false,
+            irq_pending: false,
+            irq_line: false,
+            nmi_line: false,
+            interrupts_scheduled: vec![],
+            breakpoints: HashSet::new(),
+            missing_opcodes: BTreeSet::new(),
+            rewind: None,
+            loop_landings: HashMap::new(),
+            loop_detected: None,
+            loop_device_read: Cell::new(false),
+            cycle_table: None,
+            trace_writer: None,
+        })
+    }
+
+    // the vectors live in ROM, so they have to be re-read after a bank switch that maps over them
+    pub fn reload_vectors(&mut self) -> Result {
+        self.vectors = VectorSet::read(&self.bus)?;
+        Ok(())
+    }
+
+    pub fn options(&self) -> &CpuOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut CpuOptions {
+        &mut self.options
+    }
+
+    pub fn restrict_to(&mut self, allowed: HashSet<InstructionOperation>) {
+        self.operations_allowed = Some(allowed);
+    }
+
+    pub fn events(&self) -> &[CpuEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<CpuEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // like the bus access log, once full the oldest event is dropped; a capacity of zero turns events off
+    pub fn set_events_capacity(&mut self, capacity: usize) {
+        let excess = self.events.len().saturating_sub(capacity);
+        self.events.drain(..excess);
+        self.events_capacity = capacity;
+    }
+
+    fn push_event(&mut self, event: CpuEvent) {
+        if self.events_capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.events_capacity {
+            self.events.remove(0);
+        }
+
+        self.events.push(event);
+    }
+
+    pub fn status(&self) -> CpuStatus {
+        self.status
     }
 
-    pub fn start(&mut self) -> Result {
-        while let Some(instruction) = self.determine_instruction_next() {
-            self.process_instruction(instruction)?;
+    // what WAI does on the 65C02, for front-ends idling the CPU until NMI or IRQ: nothing is fetched meanwhile,
+    // `step` and `tick` spend a cycle at a time so scheduled interrupts still come due, while `start` and `run_for`
+    // stop as they do on a halt; a halted CPU stays halted
+    pub fn wait_for_interrupt(&mut self) {
+        if self.status == CpuStatus::Running {
+            self.status = CpuStatus::WaitingForInterrupt;
         }
+    }
+
+    fn idle(&mut self) {
+        self.clock.tick(1);
+        self.bus.tick(1);
+    }
+
+    // runs until halted, a breakpoint or the `instruction_limit` option; a breakpoint at the starting PC is
+    // stepped over so a run can resume from one
+    pub fn start(&mut self) -> RunSummary {
+        debug!("starting execution at ${:04X}", self.registers.pc);
+
+        let cycles_start = self.clock.cycles();
+        let mut instructions = 0;
+
+        let stop_reason = loop {
+            if self.status != CpuStatus::Running {
+                break StopReason::Halted;
+            }
+            if self.options.instruction_limit().is_some_and(|limit| instructions >= limit) {
+                break StopReason::InstructionLimit;
+            }
+            if instructions > 0 && self.breakpoints.contains(&self.registers.pc) {
+                break StopReason::BreakpointHit(self.registers.pc);
+            }
+
+            match self.run_instruction() {
+                Ok(Some(_)) => instructions += 1,
+                Ok(None) => break StopReason::Halted,
+                Err(error) => break StopReason::Error(Rc::new(error)),
+            }
+            self.poll_interrupts();
+            if let Some(pc) = self.loop_detected {
+                self.clear_loop_detection();
+                break StopReason::InfiniteLoop(pc);
+            }
+        };
+
+        debug!("stopped execution at ${:04X} with status {:?}", self.registers.pc, self.status);
+        RunSummary { instructions, cycles: self.clock.cycles() - cycles_start, stop_reason }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    // runs one instruction, then services a pending interrupt before the next fetch
+    pub fn step(&mut self) -> Result<CpuStatus> {
+        match self.status {
+            CpuStatus::Running => {
+                self.run_instruction()?;
+                self.poll_interrupts();
+            },
+            CpuStatus::WaitingForInterrupt => {
+                if self.poll_interrupts() == 0 {
+                    self.idle();
+                }
+            },
+            CpuStatus::Halted => {},
+        }
+
+        Ok(self.status)
+    }
+
+    // Executes the next instruction on the first of its cycles and returns whether the current
+    // instruction completed on this tick. Interrupts are polled at instruction boundaries only,
+    // so one raised mid-instruction is serviced over the ticks following its completion.
+    pub fn tick(&mut self) -> Result<bool> {
+        if self.ticks_remaining == 0 {
+            if self.status == CpuStatus::Halted {
+                return Ok(false);
+            }
+
+            let mut cycles = self.poll_interrupts();
+            if cycles == 0 && self.status == CpuStatus::Running {
+                cycles = match self.run_instruction()? {
+                    Some(result) => result.cycles(),
+                    None => return Ok(false),
+                };
+            }
+            if cycles == 0 {
+                if self.status == CpuStatus::WaitingForInterrupt {
+                    self.idle();
+                }
+                return Ok(false);
+            }
+
+            self.ticks_elapsed = 0;
+            self.ticks_remaining = cycles;
+        }
+
+        self.ticks_elapsed += 1;
+        self.ticks_remaining -= 1;
+        Ok(self.ticks_remaining == 0)
+    }
+
+    pub fn ticks_this_instruction(&self) -> u8 {
+        self.ticks_elapsed
+    }
+
+    pub fn run_for(&mut self, instructions_max: u64) -> Result<u64> {
+        let mut instructions = 0;
+
+        while instructions < instructions_max && self.status == CpuStatus::Running {
+            if self.run_instruction()?.is_none() {
+                break;
+            }
+            self.poll_interrupts();
+            instructions += 1;
+        }
+
+        Ok(instructions)
+    }
+
+    // `run_for` with all instrumentation switched off for the duration, to measure the bare interpreter: hashing,
+    // the write diagnostics, loop detection, the trace writer, rewind snapshots, the access log and the counters
+    pub fn run_headless(&mut self, instructions_max: u64) -> Result<u64> {
+        let options = self.options.clone();
+        self.options
+            .set_hash_execution(false)
+            .set_detect_self_modifying_code(false)
+            .set_strict_rom(false)
+            .set_loop_detection(None);
+        let trace_writer = self.trace_writer.take();
+        let rewind = self.rewind.take();
+        self.bus.set_instrumented(false);
+
+        let result = self.run_for(instructions_max);
+
+        self.bus.set_instrumented(true);
+        self.rewind = rewind;
+        self.trace_writer = trace_writer;
+        self.options = options;
+        result
+    }
+
+    // runs the subroutine at `address` as if reached by JSR until its RTS, leaving PC on the sentinel it returned to;
+    // interrupts are not serviced meanwhile. Failing to return within `instructions_max` is an error, as is stopping
+    // or failing before then, and either puts S and PC back as they were so the sentinel is off the stack
+    pub fn call(&mut self, address: u16, instructions_max: u64) -> Result {
+        let (s, pc) = (self.registers.s, self.registers.pc);
+        self.stack_push_u16(ADDRESS_CALL_RETURN.wrapping_sub(1));
+        self.registers.pc = address;
+
+        let mut instructions = 0;
+        let result = loop {
+            if self.registers.pc == ADDRESS_CALL_RETURN && self.registers.s == s {
+                break Ok(());
+            }
+            if instructions == instructions_max {
+                let reason = anyhow!("subroutine at `${:04X}` did not return within `{}` instructions", address, instructions_max);
+                break Err(reason);
+            }
+            if self.status != CpuStatus::Running {
+                break Err(anyhow!("subroutine at `${:04X}` stopped before returning", address));
+            }
+
+            match self.run_instruction() {
+                Ok(Some(_)) => instructions += 1,
+                Ok(None) => break Err(anyhow!("subroutine at `${:04X}` stopped before returning", address)),
+                Err(error) => break Err(error),
+            }
+        };
+
+        if result.is_err() {
+            self.registers.s = s;
+            self.registers.pc = pc;
+        }
+        result
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // stays pending until interrupts are enabled
+    pub fn trigger_irq(&mut self) {
+        self.irq_pending = true;
+    }
+
+    // edge triggered: asserting the line latches an NMI for the next boundary, holding it asserted does not repeat it
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
+    }
+
+    // level triggered, unlike `trigger_irq`: the IRQ is serviced again at every boundary with I clear while asserted
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    // scheduled interrupts hold absolute cycle counts, so they move with the clock when it is set to `cycles`
+    fn rebase_scheduled_interrupts(&mut self, cycles: u64) {
+        let cycles_now = self.clock.cycles();
+        for (cycle, _) in &mut self.interrupts_scheduled {
+            *cycle = cycles + cycle.saturating_sub(cycles_now);
+        }
+    }
+
+    pub fn schedule_nmi(&mut self, cycles: u64) {
+        self.interrupts_scheduled.push((self.clock.cycles() + cycles, Interrupt::Nmi));
+    }
+
+    pub fn schedule_irq(&mut self, cycles: u64) {
+        self.interrupts_scheduled.push((self.clock.cycles() + cycles, Interrupt::Irq));
+    }
+
+    // how far the CPU can run before a scheduled interrupt is due, zero if one already is
+    pub fn cycles_until_next_scheduled_interrupt(&self) -> Option<u64> {
+        self.interrupts_scheduled.iter()
+            .map(|(cycle, _)| cycle.saturating_sub(self.clock.cycles()))
+            .min()
+    }
+
+    // the SO pin: a falling edge sets V regardless of what is executing
+    pub fn set_overflow_pin(&mut self) {
+        self.registers.p.insert(StatusFlags::OVERFLOW);
+    }
+
+    pub fn cycle_count(&self) -> u64 {
+        self.clock.cycles()
+    }
+
+    // the part of `cycle_count` spent in interrupt sequences, whether from NMI, IRQ or BRK
+    pub fn interrupt_cycle_count(&self) -> u64 {
+        self.interrupt_cycles
+    }
+
+    // scheduled interrupts keep their distance
+    pub fn reset_cycles(&mut self) {
+        self.rebase_scheduled_interrupts(0);
+        self.clock.reset();
+        self.interrupt_cycles = 0;
+    }
+
+    // filled under the `survey_unimplemented` option
+    pub fn missing_opcodes(&self) -> &BTreeSet<u8> {
+        &self.missing_opcodes
+    }
+
+    // FNV-1a over the (PC, opcode) pairs executed while `hash_execution` is enabled
+    pub fn execution_hash(&self) -> u64 {
+        self.execution_hash
+    }
+
+    // battery-backed PRG-RAM, empty when the cartridge has none
+    pub fn save_ram(&self) -> &[u8] {
+        self.bus.prg_ram()
+    }
+
+    pub fn load_save_ram(&mut self, data: &[u8]) -> Result {
+        let ram = self.bus.prg_ram_mut();
+        if data.len() != ram.len() {
+            return Err(anyhow!("expected save RAM to have length `{}`, received `{}`", ram.len(), data.len()));
+        }
+
+        ram.copy_from_slice(data);
+        Ok(())
+    }
+
+    // peeks rather than reads, so taking a snapshot mid-run leaves the bus as it was
+    pub fn save_state(&self) -> SaveState {
+        let ram = (0..state::RAM_LEN as u16).map(|address| self.bus.peek(address)).collect();
+
+        SaveState {
+            a: self.registers.a,
+            x: self.registers.x,
+            y: self.registers.y,
+            s: self.registers.s,
+            p: self.registers.p.bits(),
+            pc: self.registers.pc,
+            cycles: self.clock.cycles(),
+            ram,
+            cartridge_ram: self.bus.prg_ram().to_vec(),
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SaveState) -> Result {
+        if state.ram.len() != state::RAM_LEN {
+            return Err(anyhow!("expected RAM to have length `{}`, received `{}`", state::RAM_LEN, state.ram.len()));
+        }
+
+        // the state has to come from a machine with the same cartridge
+        let cartridge_ram_len = self.bus.prg_ram().len();
+        if state.cartridge_ram.len() != cartridge_ram_len {
+            return Err(anyhow!(
+                "expected cartridge RAM to have length `{}`, received `{}`",
+                cartridge_ram_len, state.cartridge_ram.len(),
+            ));
+        }
+
+        self.registers.a = state.a;
+        self.registers.x = state.x;
+        self.registers.y = state.y;
+        self.registers.s = state.s;
+        self.registers.p = StatusFlags::from_bits_truncate(state.p);
+        self.registers.pc = state.pc;
+        // interrupts raised on the machine being left do not carry over, those scheduled keep their distance
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.rebase_scheduled_interrupts(state.cycles);
+        self.clock.restore(state.cycles);
+        self.bus.write_n(0x0000, &state.ram)?;
+        self.bus.prg_ram_mut().copy_from_slice(&state.cartridge_ram);
+        self.status = CpuStatus::Running;
+        self.clear_loop_detection();
 
         Ok(())
     }
 
-    fn determine_instruction_next(&self) -> Option<Instruction> {
-        let opcode = self.bus.read(self.registers.pc);
-        let instruction = Instruction::from_opcode(opcode);
+    // base cycles by opcode, page crossings and decimal mode are still added on top; `None` restores the defaults
+    pub fn set_cycle_table(&mut self, table: Option<[u8; 256]>) {
+        self.cycle_table = table.map(Box::new);
+    }
+
+    // the table in use, a starting point for a custom one
+    pub fn cycle_table(&self) -> [u8; 256] {
+        self.cycle_table.as_deref().copied().unwrap_or(OPCODE_CYCLES)
+    }
+
+    // keeps the last `capacity` save states, taken every `interval` instructions; a capacity of zero turns it off
+    pub fn set_rewind(&mut self, capacity: usize, interval: u64) {
+        self.rewind = if capacity == 0 { None } else { Some(RewindBuffer::new(capacity, interval)) };
+    }
+
+    // restores the most recent save state and drops it, so repeated calls step further back; false once empty
+    pub fn rewind(&mut self) -> Result<bool> {
+        match self.rewind.as_mut().and_then(|rewind| rewind.pop()) {
+            Some(state) => self.load_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    // for conformance harnesses such as nestest, which document their own starting state
+    pub fn set_registers(&mut self, a: u8, x: u8, y: u8, s: u8, p: u8, pc: u16) {
+        self.registers = RegisterSet { a, x, y, s, p: StatusFlags::from_bits_truncate(p), pc };
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.registers.s
+    }
+
+    // the stack lives at $0100 + SP, pushes write there and then decrement
+    pub fn set_sp(&mut self, value: u8) {
+        self.registers.s = value;
+    }
+
+    pub fn trace_line(&self) -> String {
+        let mut line = String::new();
+        self.write_trace(&mut line).expect("writing to a String cannot fail");
+        line
+    }
+
+    // nestest.log layout, written straight into `out` so a buffer can be reused across instructions
+    pub fn write_trace(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let pc = self.registers.pc;
+        let (instruction, bytes) = self.peek_instruction(pc);
+        let bytes = &bytes[..instruction.len() as usize];
+
+        write!(out, "{:04X}  ", pc)?;
+        let mut column = TraceColumn::new(out);
+        for (i, byte) in bytes.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(column, "{}{:02X}", separator, byte)?;
+        }
+        column.pad(10)?;
+
+        let mut column = TraceColumn::new(out);
+        instruction.write_disassembly(&mut column, pc, bytes)?;
+        if let Ok(operand) = self.resolve_operand(instruction, bytes) {
+            Self::write_trace_annotation(&mut column, instruction, &operand)?;
+        }
+        column.pad(32)?;
+
+        write!(
+            out,
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.registers.a,
+            self.registers.x,
+            self.registers.y,
+            self.registers.p.bits(),
+            self.registers.s,
+            self.clock.cycles(),
+        )
+    }
+
+    // every instruction run from here on writes its `trace_line` to `out` before executing
+    pub fn attach_trace_writer(&mut self, out: Box<dyn std::io::Write>) {
+        self.trace_writer = Some(TraceWriter::new(out));
+    }
+
+    // flushes what is still buffered, dropping the writer does too but loses any error
+    pub fn detach_trace_writer(&mut self) -> Result {
+        match self.trace_writer.take() {
+            Some(mut writer) => Ok(writer.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    pub fn trace_state(&self) -> TraceLine {
+        TraceLine {
+            pc: self.registers.pc,
+            bytes: self.instruction_bytes(self.registers.pc),
+            a: self.registers.a,
+            x: self.registers.x,
+            y: self.registers.y,
+            p: self.registers.p.bits(),
+            sp: self.registers.s,
+            cycles: self.clock.cycles(),
+        }
+    }
+
+    // steps through `log`, comparing the state before each instruction; the first mismatch is returned
+    pub fn validate_against(&mut self, log: &[TraceLine]) -> Result<Option<TraceDivergence>> {
+        for (index, expected) in log.iter().enumerate() {
+            let actual = self.trace_state();
+            if let Some(field) = expected.first_mismatch(&actual) {
+                return Ok(Some(TraceDivergence { index, field, expected: expected.clone(), actual }));
+            }
+
+            self.step()?;
+        }
+
+        Ok(None)
+    }
+
+    // linear decode from PC, branches are not followed
+    pub fn disassemble_ahead(&self, count: usize) -> Vec<(u16, String)> {
+        let mut address = self.registers.pc;
+        let mut lines = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let (instruction, bytes) = self.peek_instruction(address);
+            let mut line = String::new();
+            instruction
+                .write_disassembly(&mut line, address, &bytes[..instruction.len() as usize])
+                .expect("writing to a String cannot fail");
+
+            lines.push((address, line));
+            address = address.wrapping_add(instruction.len() as u16);
+        }
+
+        lines
+    }
+
+    // linear sweep over `start..=end`, unofficial opcodes and instructions running past `end` are shown as data
+    pub fn disassemble_region(&self, start: u16, end: u16) -> Vec<(u16, Vec<u8>, String)> {
+        let mut lines = vec![];
+        let mut address = start as u32;
+
+        while address <= end as u32 {
+            let (instruction, bytes) = self.peek_instruction(address as u16);
+            let len = instruction.len() as u32;
+
+            if instruction.illegal() || address + len - 1 > end as u32 {
+                lines.push((address as u16, vec![bytes[0]], format!(".byte ${:02X}", bytes[0])));
+                address += 1;
+                continue;
+            }
+
+            let bytes = &bytes[..len as usize];
+            let mut line = String::new();
+            instruction
+                .write_disassembly(&mut line, address as u16, bytes)
+                .expect("writing to a String cannot fail");
+
+            lines.push((address as u16, bytes.to_vec(), line));
+            address += len;
+        }
+
+        lines
+    }
+
+    // two passes over `disassemble_region`: collect jump targets inside the region, then render them as labels;
+    // a target in the middle of an instruction or on a data byte has no line to label and is left as an address
+    pub fn disassemble_with_labels(&self, start: u16, end: u16) -> String {
+        let lines = self.disassemble_region(start, end);
+        let decoded = |bytes: &[u8]| {
+            let instruction = Instruction::decode(bytes[0]);
+            Some(instruction).filter(|instruction| !instruction.illegal() && instruction.len() as usize == bytes.len())
+        };
+        let target = |address: u16, bytes: &[u8]| {
+            decoded(bytes)?.jump_target(address, bytes).filter(|target| (start..=end).contains(target))
+        };
+
+        let starts: BTreeSet<u16> = lines.iter()
+            .filter(|(_, bytes, _)| decoded(bytes).is_some())
+            .map(|(address, _, _)| *address)
+            .collect();
+        let labels: BTreeSet<u16> = lines.iter()
+            .filter_map(|(address, bytes, _)| target(*address, bytes))
+            .filter(|target| starts.contains(target))
+            .collect();
+
+        let mut out = String::new();
+        for (address, bytes, text) in &lines {
+            if labels.contains(address) {
+                out.push_str(&format!("L_{:04X}:\n", address));
+            }
+
+            let text = match target(*address, bytes) {
+                Some(target) if labels.contains(&target) => {
+                    text.replace(&format!("${:04X}", target), &format!("L_{:04X}", target))
+                },
+                _ => text.clone(),
+            };
+            out.push_str(&format!("    {}\n", text));
+        }
+
+        out
+    }
+
+    pub fn decode(&self, address: u16) -> (InstructionOperation, Operand) {
+        let (instruction, bytes) = self.peek_instruction(address);
+        (instruction.operation(), instruction.decode_operand(&bytes[..instruction.len() as usize]))
+    }
+
+    // where the instruction at `address` reads or writes given the current registers; `None` when it has no
+    // location such as for implied, immediate and relative modes
+    pub fn decode_location(&self, address: u16) -> Option<InstructionInputLocation> {
+        let (instruction, bytes) = self.peek_instruction(address);
+        if instruction.mode() == InstructionMode::Relative {
+            return None;
+        }
+
+        let operand = OperandBytes::from_instruction(&bytes[..instruction.len() as usize]).ok()?;
+        match self.peek_input(instruction.mode(), &operand) {
+            Ok(InstructionInput::Location(location)) => Some(location),
+            _ => None,
+        }
+    }
+
+    // the address `instruction` would read or write with `operand` (the bytes after the opcode) under the current
+    // registers, without executing it; `None` for modes without one, relative included
+    pub fn effective_address(&self, instruction: &Instruction, operand: &[u8]) -> Result<Option<u16>> {
+        if instruction.mode() == InstructionMode::Relative {
+            return Ok(None);
+        }
+
+        match self.peek_input(instruction.mode(), &OperandBytes::new(operand)?)? {
+            InstructionInput::Location(location) => Ok(location.as_address()),
+            _ => Ok(None),
+        }
+    }
+
+    // the byte `instruction` would consume with `operand`, without executing it; `None` for stores, control flow and
+    // operations on registers alone
+    pub fn preview_operand_value(&self, instruction: &Instruction, operand: &[u8]) -> Result<Option<u8>> {
+        let operation = instruction.operation();
+        if operation.is_store() || operation.is_control_flow() {
+            return Ok(None);
+        }
+
+        match self.peek_input(instruction.mode(), &OperandBytes::new(operand)?)? {
+            InstructionInput::Byte(value) => Ok(Some(value)),
+            InstructionInput::Location(InstructionInputLocation::Address(address)) => Ok(Some(self.peek(address))),
+            _ => Ok(None),
+        }
+    }
+
+    // the opcode and operand of the instruction at `address`, as many bytes as its mode takes
+    pub fn instruction_bytes(&self, address: u16) -> Vec<u8> {
+        let (instruction, bytes) = self.peek_instruction(address);
+        bytes[..instruction.len() as usize].to_vec()
+    }
+
+    // decodes without moving PC or touching the bus, for tracing and disassembly
+    fn peek_instruction(&self, address: u16) -> (Instruction, [u8; 3]) {
+        let opcode = self.peek(address);
+        let instruction = Instruction::decode(opcode);
+        let len = instruction.len();
+
+        let mut bytes = [opcode, 0, 0];
+        if len > 1 {
+            bytes[1] = self.peek(address.wrapping_add(1));
+        }
+        if len > 2 {
+            bytes[2] = self.peek(address.wrapping_add(2));
+        }
+
+        (instruction, bytes)
+    }
+
+    // `bytes` holds the full instruction, opcode included
+    pub fn resolve_operand(&self, instruction: Instruction, bytes: &[u8]) -> Result<ResolvedOperand> {
+        let operand = OperandBytes::from_instruction(bytes)?;
+        let pointer = match instruction.mode() {
+            InstructionMode::Indirect => Some(operand.word()?),
+            InstructionMode::IndirectX => Some(operand.get(0)?.wrapping_add(self.registers.x) as u16),
+            InstructionMode::IndirectY => Some(self.peek_zp_u16(operand.get(0)?)),
+            _ => None,
+        };
+        let address = match self.peek_input(instruction.mode(), &operand)? {
+            InstructionInput::Location(InstructionInputLocation::Address(address)) => Some(address),
+            _ => None,
+        };
+        let value = address.map(|address| self.peek(address));
+
+        Ok(ResolvedOperand { pointer, address, value })
+    }
+
+    fn write_trace_annotation(out: &mut impl fmt::Write, instruction: Instruction, operand: &ResolvedOperand) -> fmt::Result {
+        let (address, value) = match (operand.address, operand.value) {
+            (Some(address), Some(value)) => (address, value),
+            _ => return Ok(()),
+        };
+        let pointer = operand.pointer.unwrap_or(0);
+
+        match instruction.mode() {
+            InstructionMode::Absolute => match instruction.operation() {
+                InstructionOperation::Jmp | InstructionOperation::Jsr => Ok(()),
+                _ => write!(out, " = {:02X}", value),
+            },
+            InstructionMode::ZeroPage => write!(out, " = {:02X}", value),
+            InstructionMode::ZeroPageX | InstructionMode::ZeroPageY => {
+                write!(out, " @ {:02X} = {:02X}", address, value)
+            },
+            InstructionMode::AbsoluteX | InstructionMode::AbsoluteY => {
+                write!(out, " @ {:04X} = {:02X}", address, value)
+            },
+            InstructionMode::Indirect => write!(out, " = {:04X}", address),
+            InstructionMode::IndirectX => write!(out, " @ {:02X} = {:04X} = {:02X}", pointer, address, value),
+            InstructionMode::IndirectY => write!(out, " = {:04X} @ {:04X} = {:02X}", pointer, address, value),
+            _ => Ok(()),
+        }
+    }
+
+    // fetch, decode and execute; `None` when nothing could be fetched
+    fn run_instruction(&mut self) -> Result<Option<StepResult>> {
+        if let Some(mut writer) = self.trace_writer.take() {
+            let result = writer.write(self);
+            self.trace_writer = Some(writer);
+            result?;
+        }
+
+        let (opcode, address) = self.fetch();
+        let instruction = Instruction::decode(opcode);
 
         // TODO: check if correct
-        if (self.registers.pc + instruction.len() as u16) < ADDRESS_VECTOR_NMI {
-            Some(instruction)
+        if address.saturating_add(instruction.len() as u16) >= ADDRESS_VECTOR_NMI {
+            self.registers.pc = address;
+            return Ok(None);
+        }
+
+        if self.options.hash_execution() {
+            let [pc_low, pc_high] = address.to_le_bytes();
+            for byte in &[pc_low, pc_high, opcode] {
+                self.execution_hash = (self.execution_hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        let operand = self.fetch_operand(instruction);
+        let pc_next = self.registers.pc;
+        let result = self.execute(instruction, operand)?;
+
+        if let Some(threshold) = self.options.loop_detection() {
+            if self.registers.pc != pc_next {
+                self.detect_loop(threshold);
+            }
+        }
+
+        if self.rewind.as_mut().is_some_and(|rewind| rewind.tick()) {
+            let state = self.save_state();
+            if let Some(rewind) = &mut self.rewind {
+                rewind.push(state);
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    // landings recorded before the machine state jumped elsewhere say nothing about it now
+    fn clear_loop_detection(&mut self) {
+        self.loop_landings.clear();
+        self.loop_detected = None;
+        self.loop_device_read.set(false);
+    }
+
+    // a jump landing where it landed before with nothing changed, no write or device read in between, is taken to
+    // be stuck; an interrupt that is pending or scheduled makes it a wait rather than a hang, as in `JMP *` idling
+    // for NMI or `LDA $2002; BPL` polling for vblank
+    fn detect_loop(&mut self, threshold: u32) {
+        if self.loop_device_read.replace(false) {
+            self.loop_landings.clear();
+        }
+        if self.nmi_pending || self.irq_pending || self.irq_line || !self.interrupts_scheduled.is_empty() {
+            self.loop_landings.clear();
+            return;
+        }
+
+        let registers = self.registers;
+        let landing = self.loop_landings.entry(registers.pc).or_insert((registers, 0));
+
+        if landing.0 == registers {
+            landing.1 += 1;
         } else {
-            None
+            *landing = (registers, 0);
+        }
+
+        if landing.1 >= threshold {
+            self.loop_detected = Some(registers.pc);
         }
     }
 
-    fn process_instruction(&mut self, instruction: Instruction) -> Result {
-        let len = instruction.len() as u16;
-        let bytes = self.bus.read_n(self.registers.pc, len)?;
+    // the opcode at PC and the address it was read from, PC moves past it
+    pub fn fetch(&mut self) -> (u8, u16) {
+        let address = self.registers.pc;
+        let opcode = self.bus.read(address);
+        self.registers.pc = address.wrapping_add(1);
+        (opcode, address)
+    }
 
-        // TODO: calculate final cycles
-        self.clock.tick(instruction.cycles_base());
-        self.call_instruction(instruction, &bytes)?;
-        self.registers.pc = self.registers.pc.wrapping_add(len);
+    // the bytes following a fetched opcode, PC moves past them to the next instruction
+    pub fn fetch_operand(&mut self, instruction: Instruction) -> OperandBytes {
+        let len = instruction.mode().operand_len();
+        let pc = self.registers.pc;
 
-        Ok(())
+        // unrolled rather than looped, this sits on the hot path of every instruction
+        let mut bytes = [0; 2];
+        if len > 0 {
+            bytes[0] = self.bus.read(pc);
+        }
+        if len > 1 {
+            bytes[1] = self.bus.read(pc.wrapping_add(1));
+        }
+
+        self.registers.pc = pc.wrapping_add(len as u16);
+        OperandBytes::from_array(bytes, len)
     }
 
-    fn call_instruction(&mut self, instruction: Instruction, bytes: &[u8]) -> Result {
-        let input = self.determine_input(instruction.mode(), bytes)?;
+    // services at most one pending interrupt, NMI first, and returns the cycles it took
+    fn poll_interrupts(&mut self) -> u8 {
+        let cycles = self.clock.cycles();
+        for (_, interrupt) in self.interrupts_scheduled.iter().filter(|(cycle, _)| *cycle <= cycles) {
+            match interrupt {
+                Interrupt::Nmi => self.nmi_pending = true,
+                Interrupt::Irq => self.irq_pending = true,
+            }
+        }
+        self.interrupts_scheduled.retain(|(cycle, _)| *cycle > cycles);
+
+        let vector = if self.nmi_pending {
+            self.nmi_pending = false;
+            self.vectors.nmi
+        } else if (self.irq_pending || self.irq_line) && !self.registers.p.contains(StatusFlags::INTERRUPT_DISABLE) {
+            self.irq_pending = false;
+            self.vectors.irq
+        } else {
+            return 0;
+        };
+
+        self.generate_interrupt(BreakType::Internal, vector);
+        self.registers.p.insert(StatusFlags::INTERRUPT_DISABLE);
+        self.clock.tick(CYCLES_INTERRUPT);
+        self.bus.tick(CYCLES_INTERRUPT);
+
+        if self.status == CpuStatus::WaitingForInterrupt {
+            self.status = CpuStatus::Running;
+        }
+
+        CYCLES_INTERRUPT
+    }
+
+    pub fn execute_opcode(&mut self, opcode: u8, operand: &[u8]) -> Result<StepResult> {
+        let instruction = Instruction::decode(opcode);
+        let len_expected = instruction.mode().operand_len() as usize;
+
+        if operand.len() != len_expected {
+            return Err(CpuError::InvalidOperandLength { expected: len_expected, received: operand.len() }.into());
+        }
+
+        let operand = OperandBytes::new(operand)?;
+        self.registers.pc = self.registers.pc.wrapping_add(instruction.mode().len_bytes() as u16);
+        self.execute(instruction, operand)
+    }
+
+    // PC has already moved past the instruction, jumps and branches overwrite it; on error it is left on the
+    // failing instruction
+    pub fn execute(&mut self, instruction: Instruction, operand: OperandBytes) -> Result<StepResult> {
+        let address = self.registers.pc.wrapping_sub(instruction.len() as u16);
+
+        if let Some(allowed) = &self.operations_allowed {
+            if !allowed.contains(&instruction.operation()) {
+                self.registers.pc = address;
+                return Err(CpuError::RestrictedOperation { operation: instruction.operation() }.into());
+            }
+        }
+
+        trace!("${:04X} {} {:?}", address, instruction.operation().mnemonic(), instruction.mode());
+
+        let cycles_base = match &self.cycle_table {
+            Some(table) => table[instruction.opcode() as usize],
+            None => instruction.cycles_base(),
+        };
+        let cycles = cycles_base
+            + self.determine_cycles_page_cross(&instruction, &operand)?
+            + self.determine_cycles_branch(&instruction, &operand)?
+            + self.determine_cycles_decimal(&instruction);
+        self.clock.tick(cycles);
+        self.bus.tick(cycles);
+        self.address_fetch_next = self.registers.pc;
+        self.instruction_address = address;
+        if let Err(error) = self.call_instruction(instruction, operand) {
+            self.registers.pc = address;
+            return Err(error.context(CpuError::ExecutionFailed {
+                pc: address,
+                opcode: instruction.opcode(),
+                mode: instruction.mode(),
+                operand: operand.as_slice().to_vec(),
+            }));
+        }
+
+        // catches table entries whose length disagrees with their addressing mode
+        let synchronized = instruction.operation().is_control_flow()
+            || self.registers.pc == address.wrapping_add(instruction.mode().len_bytes() as u16);
+        if !synchronized {
+            let error = CpuError::PcDesynchronized { operation: instruction.operation(), address, pc: self.registers.pc };
+            self.registers.pc = address;
+            return Err(error.into());
+        }
+
+        Ok(StepResult { instruction, cycles })
+    }
+
+    // stores and read-modify-write instructions always spend the extra cycle, so it is part of their base count
+    fn determine_cycles_page_cross(&self, instruction: &Instruction, operand: &OperandBytes) -> Result<u8> {
+        let operation = instruction.operation();
+        if operation.is_store() || operation.is_read_modify_write() {
+            return Ok(0);
+        }
+
+        let (address_base, offset) = match instruction.mode() {
+            InstructionMode::AbsoluteX => (operand.word()?, self.registers.x),
+            InstructionMode::AbsoluteY => (operand.word()?, self.registers.y),
+            // the pointer is read again by the instruction itself, only that read reaches the bus
+            InstructionMode::IndirectY => (self.peek_zp_u16(operand.get(0)?), self.registers.y),
+            _ => return Ok(0),
+        };
+        let address = address_base.wrapping_add(offset as u16);
+
+        Ok(if address_base & 0xFF00 != address & 0xFF00 { 1 } else { 0 })
+    }
+
+    // one more for a taken branch and another when the target is on a different page from the instruction after the
+    // branch, which PC already points at; the flags are checked before the branch runs
+    fn determine_cycles_branch(&self, instruction: &Instruction, operand: &OperandBytes) -> Result<u8> {
+        if !self.branch_taken(instruction.operation()) {
+            return Ok(0);
+        }
+
+        let pc = self.registers.pc;
+        let target = pc.wrapping_add(operand.get(0)? as i8 as u16);
+
+        Ok(if pc & 0xFF00 != target & 0xFF00 { 2 } else { 1 })
+    }
+
+    // whether `operation` branches under the current flags, never for anything but a branch
+    fn branch_taken(&self, operation: InstructionOperation) -> bool {
+        let p = self.registers.p;
+        match operation {
+            InstructionOperation::Bcc => !p.contains(StatusFlags::CARRY),
+            InstructionOperation::Bcs => p.contains(StatusFlags::CARRY),
+            InstructionOperation::Beq => p.contains(StatusFlags::ZERO),
+            InstructionOperation::Bmi => p.contains(StatusFlags::NEGATIVE),
+            InstructionOperation::Bne => !p.contains(StatusFlags::ZERO),
+            InstructionOperation::Bpl => !p.contains(StatusFlags::NEGATIVE),
+            InstructionOperation::Bvc => !p.contains(StatusFlags::OVERFLOW),
+            InstructionOperation::Bvs => p.contains(StatusFlags::OVERFLOW),
+            _ => false,
+        }
+    }
+
+    fn determine_cycles_decimal(&self, instruction: &Instruction) -> u8 {
+        let is_arithmetic = matches!(instruction.operation(), InstructionOperation::Adc | InstructionOperation::Sbc);
+        if is_arithmetic && self.is_decimal_mode() && self.options.variant() == CpuVariant::Cmos65C02 { 1 } else { 0 }
+    }
+
+    fn is_decimal_mode(&self) -> bool {
+        self.options.variant() != CpuVariant::Ricoh2A03 && self.registers.p.contains(StatusFlags::DECIMAL)
+    }
+
+    fn call_instruction(&mut self, instruction: Instruction, operand: OperandBytes) -> Result {
+        Self::validate_input_mode(&instruction)?;
+        let input = self.determine_input(instruction.mode(), &operand)?;
 
         match instruction.operation() {
             InstructionOperation::Adc => self.run_adc(self.resolve_input_byte(input)?),
             InstructionOperation::And => self.run_and(self.resolve_input_byte(input)?),
-            InstructionOperation::Asl => self.run_asl(input.unwrap_location()?),
-            InstructionOperation::Bcc => self.run_bcc(input.unwrap_address()?),
-            InstructionOperation::Bcs => self.run_bcs(input.unwrap_address()?),
-            InstructionOperation::Beq => self.run_beq(input.unwrap_address()?),
+            InstructionOperation::Asl => { self.run_asl(input.unwrap_location()?); },
+            InstructionOperation::Bcc => self.run_branch(InstructionOperation::Bcc, input.unwrap_address()?),
+            InstructionOperation::Bcs => self.run_branch(InstructionOperation::Bcs, input.unwrap_address()?),
+            InstructionOperation::Beq => self.run_branch(InstructionOperation::Beq, input.unwrap_address()?),
             InstructionOperation::Bit => self.run_bit(self.resolve_input_byte(input)?),
-            InstructionOperation::Bmi => self.run_bmi(input.unwrap_address()?),
-            InstructionOperation::Bne => self.run_bne(input.unwrap_address()?),
-            InstructionOperation::Bpl => self.run_bpl(input.unwrap_address()?),
+            InstructionOperation::Bmi => self.run_branch(InstructionOperation::Bmi, input.unwrap_address()?),
+            InstructionOperation::Bne => self.run_branch(InstructionOperation::Bne, input.unwrap_address()?),
+            InstructionOperation::Bpl => self.run_branch(InstructionOperation::Bpl, input.unwrap_address()?),
             InstructionOperation::Brk => self.run_brk(),
-            InstructionOperation::Bvc => self.run_bvc(input.unwrap_address()?),
-            InstructionOperation::Bvs => self.run_bvs(input.unwrap_address()?),
+            InstructionOperation::Bvc => self.run_branch(InstructionOperation::Bvc, input.unwrap_address()?),
+            InstructionOperation::Bvs => self.run_branch(InstructionOperation::Bvs, input.unwrap_address()?),
             InstructionOperation::Clc => self.run_clc(),
             InstructionOperation::Cld => self.run_cld(),
             InstructionOperation::Cli => self.run_cli(),
@@ -104,20 +1108,21 @@ impl Cpu {
             InstructionOperation::Inc => self.run_inc(input.unwrap_address()?),
             InstructionOperation::Inx => self.run_inx(),
             InstructionOperation::Iny => self.run_iny(),
+            InstructionOperation::Jam => self.run_jam(),
             InstructionOperation::Jmp => self.run_jmp(input.unwrap_address()?),
-            InstructionOperation::Jsr => self.run_jsr(input.unwrap_address()?, instruction.len()),
+            InstructionOperation::Jsr => self.run_jsr(input.unwrap_address()?),
             InstructionOperation::Lda => self.run_lda(self.resolve_input_byte(input)?),
             InstructionOperation::Ldx => self.run_ldx(self.resolve_input_byte(input)?),
             InstructionOperation::Ldy => self.run_ldy(self.resolve_input_byte(input)?),
-            InstructionOperation::Lsr => self.run_lsr(input.unwrap_location()?),
+            InstructionOperation::Lsr => { self.run_lsr(input.unwrap_location()?); },
             InstructionOperation::Nop => {},
             InstructionOperation::Ora => self.run_ora(self.resolve_input_byte(input)?),
             InstructionOperation::Pha => self.run_pha(),
             InstructionOperation::Php => self.run_php(),
             InstructionOperation::Pla => self.run_pla(),
             InstructionOperation::Plp => self.run_plp(),
-            InstructionOperation::Rol => self.run_rol(input.unwrap_location()?),
-            InstructionOperation::Ror => self.run_ror(input.unwrap_location()?),
+            InstructionOperation::Rol => { self.run_rol(input.unwrap_location()?); },
+            InstructionOperation::Ror => { self.run_ror(input.unwrap_location()?); },
             InstructionOperation::Rti => self.run_rti(),
             InstructionOperation::Rts => self.run_rts(),
             InstructionOperation::Sbc => self.run_sbc(self.resolve_input_byte(input)?),
@@ -133,72 +1138,110 @@ impl Cpu {
             InstructionOperation::Txa => self.run_txa(),
             InstructionOperation::Txs => self.run_txs(),
             InstructionOperation::Tya => self.run_tya(),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Alr => self.run_alr(self.resolve_input_byte(input)?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Anc => self.run_anc(self.resolve_input_byte(input)?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Arr => self.run_arr(self.resolve_input_byte(input)?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Rla => self.run_rla(input.unwrap_address()?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Rra => self.run_rra(input.unwrap_address()?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Slo => self.run_slo(input.unwrap_address()?),
+            #[cfg(feature = "illegal-opcodes")]
+            InstructionOperation::Sre => self.run_sre(input.unwrap_address()?),
+            #[cfg(not(feature = "illegal-opcodes"))]
+            InstructionOperation::Alr
+                | InstructionOperation::Anc
+                | InstructionOperation::Arr
+                | InstructionOperation::Rla
+                | InstructionOperation::Rra
+                | InstructionOperation::Slo
+                | InstructionOperation::Sre
+                => self.run_unimplemented(instruction, "requires the `illegal-opcodes` feature")?,
+            InstructionOperation::Ahx
+                | InstructionOperation::Axs
+                | InstructionOperation::Dcp
+                | InstructionOperation::Isb
+                | InstructionOperation::Las
+                | InstructionOperation::Lax
+                | InstructionOperation::Sax
+                | InstructionOperation::Shx
+                | InstructionOperation::Shy
+                | InstructionOperation::Tas
+                | InstructionOperation::Xaa
+                => self.run_unimplemented(instruction, "is not implemented")?,
+        }
+
+        Ok(())
+    }
+
+    fn validate_input_mode(instruction: &Instruction) -> Result {
+        let operation = instruction.operation();
+        let mode = instruction.mode();
+
+        if mode == InstructionMode::Immediate && (operation.is_store() || operation.is_read_modify_write()) {
+            return Err(CpuError::InvalidInputMode { operation, mode }.into());
         }
 
         Ok(())
     }
 
-    fn determine_input(&self, mode: InstructionMode, bytes: &[u8]) -> Result<InstructionInput> {
+    fn determine_input(&self, mode: InstructionMode, operand: &OperandBytes) -> Result<InstructionInput> {
+        self.determine_input_with(mode, operand, |address| self.bus.read(address))
+    }
+
+    // `determine_input` for previews and tracing, indirect pointers are peeked rather than read
+    fn peek_input(&self, mode: InstructionMode, operand: &OperandBytes) -> Result<InstructionInput> {
+        self.determine_input_with(mode, operand, |address| self.peek(address))
+    }
+
+    fn determine_input_with(
+        &self,
+        mode: InstructionMode,
+        operand: &OperandBytes,
+        read: impl Fn(u16) -> u8,
+    ) -> Result<InstructionInput> {
         let input = match mode {
             InstructionMode::Implied => InstructionInput::Implied,
             InstructionMode::Accumulator => {
                 InstructionInput::Location(InstructionInputLocation::Accumulator)
             },
-            InstructionMode::Immediate => {
-                Self::assert_input_len(2, bytes);
-                InstructionInput::Byte(bytes[1])
-            },
+            InstructionMode::Immediate => InstructionInput::Byte(operand.get(0)?),
             InstructionMode::Relative => {
-                Self::assert_input_len(2, bytes);
-                let offset = i32::from(bytes[1] as i8);
+                let offset = i32::from(operand.get(0)? as i8);
                 let address = (self.registers.pc as i32).wrapping_add(offset) as u16;
                 InstructionInput::from_address(address)
             },
-            InstructionMode::ZeroPage => {
-                Self::assert_input_len(2, bytes);
-                InstructionInput::from_address(bytes[1] as u16)
-            },
+            InstructionMode::ZeroPage => InstructionInput::from_address(operand.get(0)? as u16),
             InstructionMode::ZeroPageX => {
-                Self::assert_input_len(2, bytes);
-                let address = bytes[1].wrapping_add(self.registers.x) as u16;
+                let address = operand.get(0)?.wrapping_add(self.registers.x) as u16;
                 InstructionInput::from_address(address)
             },
             InstructionMode::ZeroPageY => {
-                Self::assert_input_len(2, bytes);
-                let address = bytes[1].wrapping_add(self.registers.y) as u16;
-                InstructionInput::from_address(address)
-            },
-            InstructionMode::Absolute => {
-                Self::assert_input_len(3, bytes);
-                let address = u16::from_le_bytes([bytes[1], bytes[2]]);
+                let address = operand.get(0)?.wrapping_add(self.registers.y) as u16;
                 InstructionInput::from_address(address)
             },
+            InstructionMode::Absolute => InstructionInput::from_address(operand.word()?),
             InstructionMode::AbsoluteX => {
-                let input = self.determine_input(InstructionMode::Absolute, bytes)?;
-                let address = input.unwrap_address()?.wrapping_add(self.registers.x as u16);
+                let address = operand.word()?.wrapping_add(self.registers.x as u16);
                 InstructionInput::from_address(address)
             },
             InstructionMode::AbsoluteY => {
-                let input = self.determine_input(InstructionMode::Absolute, bytes)?;
-                let address = input.unwrap_address()?.wrapping_add(self.registers.y as u16);
+                let address = operand.word()?.wrapping_add(self.registers.y as u16);
                 InstructionInput::from_address(address)
             },
             InstructionMode::Indirect => {
-                Self::assert_input_len(3, bytes);
-                let address_indirect = u16::from_le_bytes([bytes[1], bytes[2]]);
-                let address = self.bus.read_u16(address_indirect)?;
-                InstructionInput::from_address(address)
+                InstructionInput::from_address(Bus::read_u16_within_page_with(operand.word()?, &read))
             },
             InstructionMode::IndirectX => {
-                Self::assert_input_len(2, bytes);
-                let address_indirect = bytes[1].wrapping_add(self.registers.x) as u16;
-                let address = self.bus.read_u16(address_indirect)?;
-                InstructionInput::from_address(address)
+                let address_indirect = operand.get(0)?.wrapping_add(self.registers.x);
+                InstructionInput::from_address(Bus::read_zp_u16_with(address_indirect, &read))
             },
             InstructionMode::IndirectY => {
-                Self::assert_input_len(2, bytes);
-                let address = self.bus.read_u16(bytes[1].into())?
-                    .wrapping_add(self.registers.y as u16);
+                let address = Bus::read_zp_u16_with(operand.get(0)?, &read).wrapping_add(self.registers.y as u16);
                 InstructionInput::from_address(address)
             },
         };
@@ -206,24 +1249,30 @@ impl Cpu {
         Ok(input)
     }
 
+    // a zero page pointer, without touching the bus
+    fn peek_zp_u16(&self, address: u8) -> u16 {
+        Bus::read_zp_u16_with(address, |address| self.peek(address))
+    }
+
     fn resolve_input_byte(&self, input: InstructionInput) -> Result<u8> {
         let value = match input {
             InstructionInput::Byte(value) => value,
-            InstructionInput::Location(location) => match location {
-                InstructionInputLocation::Address(address) => self.bus.read(address),
-                _ => return Err(anyhow!("cannot resolve input byte for the current variant")),
-            },
+            InstructionInput::Location(InstructionInputLocation::Address(address)) => self.read(address),
             _ => return Err(anyhow!("cannot resolve input byte for the current variant")),
         };
 
         Ok(value)
     }
 
-    fn assert_input_len(len_expected: usize, bytes: &[u8]) {
-        assert!(len_expected == bytes.len(), "expected args to have length `{}`, received `{}`", len_expected, bytes.len());
+    fn run_adc(&mut self, input: u8) {
+        if self.is_decimal_mode() {
+            self.run_adc_decimal(input);
+        } else {
+            self.run_adc_binary(input);
+        }
     }
 
-    fn run_adc(&mut self, input: u8) {
+    fn run_adc_binary(&mut self, input: u8) {
         let a_old = self.registers.a;
         let carry = (self.registers.p & StatusFlags::CARRY).bits();
         let result = self.registers.a.wrapping_add(input).wrapping_add(carry);
@@ -234,16 +1283,45 @@ impl Cpu {
         self.set_status_flag_negative(result);
     }
 
+    fn run_adc_decimal(&mut self, input: u8) {
+        let a = self.registers.a as u16;
+        let input = input as u16;
+        let carry = (self.registers.p & StatusFlags::CARRY).bits() as u16;
+
+        let mut low = (a & 0x0F) + (input & 0x0F) + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut result = (a & 0xF0) + (input & 0xF0) + low;
+        // NMOS takes N and V from the sum before the high nibble is adjusted, and Z from the binary sum
+        let intermediate = result as u8;
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+
+        self.registers.a = result as u8;
+        self.registers.p.set(StatusFlags::CARRY, result >= 0x100);
+        self.registers.p.set(StatusFlags::OVERFLOW, !(a ^ input) & (a ^ intermediate as u16) & 0x80 != 0);
+
+        if self.options.variant() == CpuVariant::Cmos65C02 {
+            self.set_status_flag_zero(self.registers.a);
+            self.set_status_flag_negative(self.registers.a);
+        } else {
+            self.set_status_flag_zero((a + input + carry) as u8);
+            self.set_status_flag_negative(intermediate);
+        }
+    }
+
     fn run_and(&mut self, input: u8) {
         self.registers.a &= input;
         self.set_status_flag_zero(self.registers.a);
         self.set_status_flag_negative(self.registers.a);
     }
 
-    fn run_asl(&mut self, target: InstructionInputLocation) {
+    fn run_asl(&mut self, target: InstructionInputLocation) -> u8 {
         let input = match target {
             InstructionInputLocation::Accumulator => self.registers.a,
-            InstructionInputLocation::Address(address) => self.bus.read(address),
+            InstructionInputLocation::Address(address) => self.read(address),
         };
         let result = input.wrapping_shl(1);
         self.persist_result_by_location(result, target);
@@ -251,22 +1329,13 @@ impl Cpu {
         self.set_status_flag_carry(input, result);
         self.set_status_flag_zero(result);
         self.set_status_flag_negative(result);
-    }
 
-    fn run_bcc(&mut self, target: u16) {
-        if !self.registers.p.contains(StatusFlags::CARRY) {
-            self.registers.pc = target;
-        }
+        result
     }
 
-    fn run_bcs(&mut self, target: u16) {
-        if self.registers.p.contains(StatusFlags::CARRY) {
-            self.registers.pc = target;
-        }
-    }
-
-    fn run_beq(&mut self, target: u16) {
-        if self.registers.p.contains(StatusFlags::ZERO) {
+    // BCC through BVS, the condition shared with the cycle count through `branch_taken`
+    fn run_branch(&mut self, operation: InstructionOperation, target: u16) {
+        if self.branch_taken(operation) {
             self.registers.pc = target;
         }
     }
@@ -277,43 +1346,26 @@ impl Cpu {
         self.registers.p.set(StatusFlags::NEGATIVE, input.is_bit_set(7));
     }
 
-    fn run_bmi(&mut self, target: u16) {
-        if self.registers.p.contains(StatusFlags::NEGATIVE) {
-            self.registers.pc = target;
-        }
-    }
-
-    fn run_bne(&mut self, target: u16) {
-        if !self.registers.p.contains(StatusFlags::ZERO) {
-            self.registers.pc = target;
-        }
-    }
-
-    fn run_bpl(&mut self, target: u16) {
-        if !self.registers.p.contains(StatusFlags::NEGATIVE) {
-            self.registers.pc = target;
-        }
-    }
-
     fn run_brk(&mut self) {
-        if !self.registers.p.contains(StatusFlags::INTERRUPT_DISABLE) {
-            self.generate_interrupt(BreakType::Program);
-
-            // TODO: hacky, find better way to account for instruction length being added
-            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        if self.options.break_halts() {
+            self.registers.pc = self.instruction_address;
+            self.status = CpuStatus::Halted;
+            return;
         }
-    }
 
-    fn run_bvc(&mut self, target: u16) {
-        if !self.registers.p.contains(StatusFlags::OVERFLOW) {
-            self.registers.pc = target;
-        }
-    }
+        // BRK is not masked by I; an NMI raised during the sequence hijacks it, BRK then takes the NMI vector and
+        // the NMI is not serviced separately
+        let vector = if self.nmi_pending {
+            self.nmi_pending = false;
+            self.vectors.nmi
+        } else {
+            self.vectors.irq
+        };
 
-    fn run_bvs(&mut self, target: u16) {
-        if self.registers.p.contains(StatusFlags::OVERFLOW) {
-            self.registers.pc = target;
-        }
+        // TODO: the 6502 pushes the address past the padding byte, not that of BRK itself
+        self.registers.pc = self.instruction_address;
+        self.generate_interrupt(BreakType::Program, vector);
+        self.registers.p.insert(StatusFlags::INTERRUPT_DISABLE);
     }
 
     fn run_clc(&mut self) {
@@ -333,29 +1385,20 @@ impl Cpu {
     }
 
     fn run_cmp(&mut self, input: u8) {
-        let result = self.registers.a.wrapping_sub(input);
-        self.registers.p.set(StatusFlags::CARRY, self.registers.a >= input);
-        self.registers.p.set(StatusFlags::ZERO, self.registers.a == input);
-        self.registers.p.set(StatusFlags::NEGATIVE, result.is_bit_set(7));
+        self.compare(self.registers.a, input);
     }
 
     fn run_cpx(&mut self, input: u8) {
-        let result = self.registers.x.wrapping_sub(input);
-        self.registers.p.set(StatusFlags::CARRY, self.registers.x >= input);
-        self.registers.p.set(StatusFlags::ZERO, self.registers.x == input);
-        self.registers.p.set(StatusFlags::NEGATIVE, result.is_bit_set(7));
+        self.compare(self.registers.x, input);
     }
 
     fn run_cpy(&mut self, input: u8) {
-        let result = self.registers.y.wrapping_sub(input);
-        self.registers.p.set(StatusFlags::CARRY, self.registers.y >= input);
-        self.registers.p.set(StatusFlags::ZERO, self.registers.y == input);
-        self.registers.p.set(StatusFlags::NEGATIVE, result.is_bit_set(7));
+        self.compare(self.registers.y, input);
     }
 
     fn run_dec(&mut self, target: u16) {
-        let result = self.bus.read(target).wrapping_sub(1);
-        self.bus.write(target, result);
+        let result = self.read(target).wrapping_sub(1);
+        self.write(target, result);
         self.set_status_flag_zero(result);
         self.set_status_flag_negative(result);
     }
@@ -379,8 +1422,8 @@ impl Cpu {
     }
 
     fn run_inc(&mut self, target: u16) {
-        let result = self.bus.read(target).wrapping_add(1);
-        self.bus.write(target, result);
+        let result = self.read(target).wrapping_add(1);
+        self.write(target, result);
         self.set_status_flag_zero(result);
         self.set_status_flag_negative(result);
     }
@@ -397,17 +1440,34 @@ impl Cpu {
         self.set_status_flag_negative(self.registers.y);
     }
 
-    fn run_jmp(&mut self, target: u16) {
-        // TODO: hacky, find better way to account for instruction length being added
-        self.registers.pc = target - 3;
+    fn run_unimplemented(&mut self, instruction: Instruction, reason: &'static str) -> Result {
+        if self.options.survey_unimplemented() {
+            self.missing_opcodes.insert(instruction.opcode());
+            return Ok(());
+        }
+
+        Err(CpuError::UnimplementedOperation { operation: instruction.operation(), reason }.into())
+    }
+
+    fn run_jam(&mut self) {
+        // the CPU locks up with PC stuck on the jam opcode until it is reset
+        self.registers.pc = self.instruction_address;
+        warn!("jam opcode halted the CPU at ${:04X}", self.registers.pc);
+        self.status = CpuStatus::Halted;
     }
 
-    fn run_jsr(&mut self, target: u16, instruction_len: u8) {
-        let stack_address = self.registers.pc.wrapping_add(instruction_len as u16).wrapping_sub(1);
-        self.stack_push_u16(stack_address);
+    fn run_jmp(&mut self, target: u16) {
+        if self.options.jump_to_self_halts() && target == self.instruction_address {
+            self.status = CpuStatus::Halted;
+        }
+
+        self.registers.pc = target;
+    }
 
-        // TODO: hacky, find better way to account for instruction length being added
-        self.registers.pc = target - instruction_len as u16;
+    // pushes the address of its own last byte, RTS adds the one that is missing
+    fn run_jsr(&mut self, target: u16) {
+        self.stack_push_u16(self.registers.pc.wrapping_sub(1));
+        self.registers.pc = target;
     }
 
     fn run_lda(&mut self, input: u8) {
@@ -428,10 +1488,10 @@ impl Cpu {
         self.set_status_flag_negative(input);
     }
 
-    fn run_lsr(&mut self, target: InstructionInputLocation) {
+    fn run_lsr(&mut self, target: InstructionInputLocation) -> u8 {
         let input = match target {
             InstructionInputLocation::Accumulator => self.registers.a,
-            InstructionInputLocation::Address(address) => self.bus.read(address),
+            InstructionInputLocation::Address(address) => self.read(address),
         };
         let result = input.wrapping_shr(1);
         self.persist_result_by_location(result, target);
@@ -441,6 +1501,8 @@ impl Cpu {
 
         // TODO: is this correct? bit 7 seems to never be set
         self.registers.p.remove(StatusFlags::NEGATIVE);
+
+        result
     }
 
     fn run_ora(&mut self, input: u8) {
@@ -464,13 +1526,13 @@ impl Cpu {
     }
 
     fn run_plp(&mut self) {
-        self.registers.p = StatusFlags::from_bits(self.stack_pull()).unwrap();
+        self.stack_pull_status();
     }
 
-    fn run_rol(&mut self, target: InstructionInputLocation) {
+    fn run_rol(&mut self, target: InstructionInputLocation) -> u8 {
         let input = match target {
             InstructionInputLocation::Accumulator => self.registers.a,
-            InstructionInputLocation::Address(address) => self.bus.read(address),
+            InstructionInputLocation::Address(address) => self.read(address),
         };
         let carry = (self.registers.p & StatusFlags::CARRY).bits();
         let result = input.wrapping_shl(1) + carry;
@@ -479,12 +1541,14 @@ impl Cpu {
         self.registers.p.set(StatusFlags::CARRY, input.is_bit_set(7));
         self.set_status_flag_zero(result);
         self.set_status_flag_negative(result);
+
+        result
     }
 
-    fn run_ror(&mut self, target: InstructionInputLocation) {
+    fn run_ror(&mut self, target: InstructionInputLocation) -> u8 {
         let input = match target {
             InstructionInputLocation::Accumulator => self.registers.a,
-            InstructionInputLocation::Address(address) => self.bus.read(address),
+            InstructionInputLocation::Address(address) => self.read(address),
         };
         let carry = (self.registers.p & StatusFlags::CARRY).bits();
         let result = input.wrapping_shr(1) + (carry << 7);
@@ -493,25 +1557,49 @@ impl Cpu {
         self.registers.p.set(StatusFlags::CARRY, input.is_bit_set(0));
         self.set_status_flag_zero(result);
         self.set_status_flag_negative(result);
+
+        result
     }
 
     fn run_rti(&mut self) {
-        self.registers.p = StatusFlags::from_bits(self.stack_pull()).unwrap();
-
-        // TODO: hacky, find better way to account for instruction length being added
-        self.registers.pc = self.stack_pull_u16().wrapping_sub(1);
+        self.stack_pull_status();
+        self.registers.pc = self.stack_pull_u16();
     }
 
     fn run_rts(&mut self) {
-        let address = self.stack_pull_u16();
-
-        // TODO: hacky, find better way to account for instruction length being added
-        self.registers.pc = address.wrapping_sub(1);
+        self.registers.pc = self.stack_pull_u16().wrapping_add(1);
     }
 
     // TODO: figure out how exactly this bad boy works with flags C/V
     fn run_sbc(&mut self, input: u8) {
-        self.run_adc(!input);
+        if self.is_decimal_mode() {
+            self.run_sbc_decimal(input);
+        } else {
+            self.run_adc_binary(!input);
+        }
+    }
+
+    // NMOS sets every flag as the binary subtraction would, only A is decimal adjusted
+    fn run_sbc_decimal(&mut self, input: u8) {
+        let a = self.registers.a as i16;
+        let carry = (self.registers.p & StatusFlags::CARRY).bits() as i16;
+        self.run_adc_binary(!input);
+        let input = input as i16;
+
+        let mut low = (a & 0x0F) - (input & 0x0F) + carry - 1;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (a & 0xF0) - (input & 0xF0) + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        self.registers.a = result as u8;
+        if self.options.variant() == CpuVariant::Cmos65C02 {
+            self.set_status_flag_zero(self.registers.a);
+            self.set_status_flag_negative(self.registers.a);
+        }
     }
 
     fn run_sec(&mut self) {
@@ -527,15 +1615,15 @@ impl Cpu {
     }
 
     fn run_sta(&mut self, target: u16) {
-        self.bus.write(target, self.registers.a);
+        self.write(target, self.registers.a);
     }
 
     fn run_stx(&mut self, target: u16) {
-        self.bus.write(target, self.registers.x);
+        self.write(target, self.registers.x);
     }
 
     fn run_sty(&mut self, target: u16) {
-        self.bus.write(target, self.registers.y);
+        self.write(target, self.registers.y);
     }
 
     fn run_tax(&mut self) {
@@ -572,6 +1660,66 @@ impl Cpu {
         self.set_status_flag_negative(self.registers.a);
     }
 
+    // AND then LSR A
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_alr(&mut self, input: u8) {
+        self.run_and(input);
+        self.run_lsr(InstructionInputLocation::Accumulator);
+    }
+
+    // AND, with bit 7 of the result also copied into carry
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_anc(&mut self, input: u8) {
+        self.run_and(input);
+        self.registers.p.set(StatusFlags::CARRY, self.registers.a.is_bit_set(7));
+    }
+
+    // AND then ROR A, but carry and overflow come from bits 6 and 5 of the result rather than the shift
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_arr(&mut self, input: u8) {
+        self.run_and(input);
+        self.run_ror(InstructionInputLocation::Accumulator);
+
+        let result = self.registers.a;
+        self.registers.p.set(StatusFlags::CARRY, result.is_bit_set(6));
+        self.registers.p.set(StatusFlags::OVERFLOW, result.is_bit_set(6) != result.is_bit_set(5));
+    }
+
+    // ROL then AND, like the other RMW combos the accumulator operation takes the value written back
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_rla(&mut self, address: u16) {
+        let result = self.run_rol(InstructionInputLocation::Address(address));
+        self.run_and(result);
+    }
+
+    // ROR then ADC, which adds the carry shifted out by the ROR
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_rra(&mut self, address: u16) {
+        let result = self.run_ror(InstructionInputLocation::Address(address));
+        self.run_adc(result);
+    }
+
+    // ASL then ORA
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_slo(&mut self, address: u16) {
+        let result = self.run_asl(InstructionInputLocation::Address(address));
+        self.run_ora(result);
+    }
+
+    // LSR then EOR
+    #[cfg(feature = "illegal-opcodes")]
+    fn run_sre(&mut self, address: u16) {
+        let result = self.run_lsr(InstructionInputLocation::Address(address));
+        self.run_eor(result);
+    }
+
+    fn compare(&mut self, register: u8, input: u8) {
+        let result = register.wrapping_sub(input);
+        self.registers.p.set(StatusFlags::CARRY, register >= input);
+        self.registers.p.set(StatusFlags::ZERO, register == input);
+        self.registers.p.set(StatusFlags::NEGATIVE, result.is_bit_set(7));
+    }
+
     fn set_status_flag_carry(&mut self, input: u8, result: u8) {
         self.registers.p.set(StatusFlags::CARRY, result < input);
     }
@@ -591,32 +1739,85 @@ impl Cpu {
     fn persist_result_by_location(&mut self, result: u8, target: InstructionInputLocation) {
         match target {
             InstructionInputLocation::Accumulator => self.registers.a = result,
-            InstructionInputLocation::Address(address) => self.bus.write(address, result),
+            InstructionInputLocation::Address(address) => self.write(address, result),
+        }
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        if !self.loop_landings.is_empty() && self.bus.is_device(address) {
+            self.loop_device_read.set(true);
+        }
+
+        self.read_cycle_timer(address).unwrap_or_else(|| self.bus.read(address))
+    }
+
+    // `read` without side effects on the bus, for previews and tracing
+    fn peek(&self, address: u16) -> u8 {
+        self.read_cycle_timer(address).unwrap_or_else(|| self.bus.peek(address))
+    }
+
+    fn read_cycle_timer(&self, address: u16) -> Option<u8> {
+        if !self.options.cycle_timer() || !ADDRESS_CYCLE_TIMER.contains(&address) {
+            return None;
+        }
+
+        Some(self.clock.cycles().to_le_bytes()[(address - ADDRESS_CYCLE_TIMER.start()) as usize])
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if self.options.strict_rom() && self.bus.is_rom(address) {
+            self.push_event(CpuEvent::RomWrite { pc: self.instruction_address, address, value });
+        }
+
+        self.bus.write(address, value);
+
+        if !self.loop_landings.is_empty() {
+            self.loop_landings.clear();
+        }
+
+        // an instruction is at most 3 bytes long, so this covers everything the next fetch reads
+        if self.options.detect_self_modifying_code() && address.wrapping_sub(self.address_fetch_next) < 3 {
+            self.push_event(CpuEvent::SelfModifyingWrite { pc: self.instruction_address, address, value });
         }
     }
 
+    // only the six real flags come from the stack, the break bits stay under the CPU's control with bit 5 set
+    fn stack_pull_status(&mut self) {
+        let pulled = StatusFlags::from_bits_truncate(self.stack_pull());
+        let breaks = StatusFlags::BREAK_LEFT | StatusFlags::BREAK_RIGHT;
+        self.registers.p = (pulled - breaks) | (self.registers.p & StatusFlags::BREAK_RIGHT) | StatusFlags::BREAK_LEFT;
+    }
+
     fn stack_push(&mut self, value: u8) {
-        self.bus.write(self.stack_determine_address(), value);
+        if self.registers.s == 0x00 {
+            warn!("stack overflow at ${:04X}", self.instruction_address);
+        }
+
+        self.write(self.stack_determine_address(), value);
         self.registers.s = self.registers.s.wrapping_sub(1);
     }
 
+    // pushed as two single bytes (high byte first) so each one wraps within the stack page
     fn stack_push_u16(&mut self, value: u16) {
         let bytes = value.to_le_bytes();
-        self.stack_push(bytes[0]);
         self.stack_push(bytes[1]);
+        self.stack_push(bytes[0]);
     }
 
     fn stack_pull(&mut self) -> u8 {
-        let address = self.stack_determine_address().wrapping_add(1);
-        let value = self.bus.read(address);
-        self.bus.write(address, 0);
+        if self.registers.s == 0xFF {
+            warn!("stack underflow at ${:04X}", self.instruction_address);
+        }
+
         self.registers.s = self.registers.s.wrapping_add(1);
+        let address = self.stack_determine_address();
+        let value = self.read(address);
+        self.write(address, 0);
         value
     }
 
     fn stack_pull_u16(&mut self) -> u16 {
-        let mut bytes = [self.stack_pull(), self.stack_pull()];
-        bytes.reverse();
+        let bytes = [self.stack_pull(), self.stack_pull()];
         u16::from_le_bytes(bytes)
     }
 
@@ -625,15 +1826,70 @@ impl Cpu {
     }
 
     // TODO: unit test separately?
-    fn generate_interrupt(&mut self, break_type: BreakType) {
+    // the break bits only exist in the pushed copy of P, telling BRK apart from NMI and IRQ
+    fn generate_interrupt(&mut self, break_type: BreakType, vector: u16) {
+        let mut p = self.registers.p;
+        p.set_break(break_type);
+
         self.stack_push_u16(self.registers.pc);
-        self.stack_push(self.registers.p.bits());
-        self.registers.pc = self.vectors.irq;
-        self.registers.p.set_break(break_type);
+        self.stack_push(p.bits());
+        self.registers.pc = vector;
+        self.interrupt_cycles += CYCLES_INTERRUPT as u64;
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+struct TraceColumn<'a, W: fmt::Write> {
+    out: &'a mut W,
+    len: usize,
+}
+
+impl<'a, W: fmt::Write> TraceColumn<'a, W> {
+    fn new(out: &'a mut W) -> Self {
+        Self { out, len: 0 }
+    }
+
+    fn pad(&mut self, width: usize) -> fmt::Result {
+        for _ in self.len..width {
+            self.out.write_char(' ')?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for TraceColumn<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.len();
+        self.out.write_str(s)
+    }
+}
+
+// `pointer` is the intermediate address an indirect mode went through: the pointer operand for
+// Indirect, the zero-page location for IndirectX and the un-indexed base address for IndirectY
+#[derive(Debug, PartialEq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct ResolvedOperand {
+    pointer: Option<u16>,
+    address: Option<u16>,
+    value: Option<u8>,
+}
+
+#[derive(Debug, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct StepResult {
+    instruction: Instruction,
+    cycles: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CpuStatus {
+    Running,
+    Halted,
+    // entered through `wait_for_interrupt`, left again once an interrupt is serviced
+    WaitingForInterrupt,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 struct RegisterSet {
     a: u8,
     x: u8,
@@ -662,8 +1918,18 @@ struct VectorSet {
     irq: u16,
 }
 
+impl VectorSet {
+    fn read(bus: &Bus) -> Result<Self> {
+        Ok(Self {
+            nmi: bus.read_u16(ADDRESS_VECTOR_NMI)?,
+            reset: bus.read_u16(ADDRESS_VECTOR_RESET)?,
+            irq: bus.read_u16(ADDRESS_VECTOR_IRQ)?,
+        })
+    }
+}
+
 bitflags! {
-    struct StatusFlags: u8 {
+    pub struct StatusFlags: u8 {
         const NEGATIVE = 0b1000_0000;
         const OVERFLOW = 0b0100_0000;
         const BREAK_LEFT = 0b0010_0000;
@@ -676,7 +1942,27 @@ bitflags! {
 }
 
 impl StatusFlags {
-    pub fn set_break(&mut self, break_type: BreakType) {
+    // set flags are shown in uppercase, clear ones in lowercase; bit 5 has no name
+    pub fn to_display_string(&self) -> String {
+        "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, name)| if self.bits().is_bit_set(7 - i as u8) { name } else { name.to_ascii_lowercase() })
+            .collect()
+    }
+
+    pub fn decompose(&self) -> FlagStates {
+        FlagStates {
+            negative: self.contains(StatusFlags::NEGATIVE),
+            overflow: self.contains(StatusFlags::OVERFLOW),
+            decimal: self.contains(StatusFlags::DECIMAL),
+            interrupt: self.contains(StatusFlags::INTERRUPT_DISABLE),
+            zero: self.contains(StatusFlags::ZERO),
+            carry: self.contains(StatusFlags::CARRY),
+        }
+    }
+
+    fn set_break(&mut self, break_type: BreakType) {
         match break_type {
             BreakType::Internal => {
                 self.insert(StatusFlags::BREAK_LEFT);
@@ -690,6 +1976,17 @@ impl StatusFlags {
     }
 }
 
+// the flags by name, the break bits are left out as they only exist on the stack
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct FlagStates {
+    pub negative: bool,
+    pub overflow: bool,
+    pub decimal: bool,
+    pub interrupt: bool,
+    pub zero: bool,
+    pub carry: bool,
+}
+
 impl Default for StatusFlags {
     fn default() -> Self {
         let mut flags = Self::empty();
@@ -698,6 +1995,12 @@ impl Default for StatusFlags {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Interrupt {
+    Nmi,
+    Irq,
+}
+
 enum BreakType {
     Internal,
     Program,
@@ -0,0 +1,33 @@
+#[derive(Debug, Default, Clone, CopyGetters, Setters)]
+#[getset(get_copy = "pub", set = "pub")]
+pub struct CpuOptions {
+    detect_self_modifying_code: bool,
+    hash_execution: bool,
+    // stop on BRK instead of jumping through the IRQ vector, for bare-metal test binaries
+    break_halts: bool,
+    // treat `JMP *` as the end of the program, the usual way test binaries signal they are done
+    jump_to_self_halts: bool,
+    // report writes to cartridge ROM, which are otherwise dropped silently
+    strict_rom: bool,
+    // upper bound on the instructions a single `start` may run
+    instruction_limit: Option<u64>,
+    variant: CpuVariant,
+    // reads of $4020-$4023 return the low 32 bits of the cycle count, for test ROMs timing themselves
+    cycle_timer: bool,
+    // record opcodes without a handler in `missing_opcodes` and step over them, instead of failing
+    survey_unimplemented: bool,
+    // stop `start` once a jump lands on the same PC with the same registers this many times running, unless
+    // something was written in between
+    loop_detection: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum CpuVariant {
+    // the NES CPU, which has the decimal flag but no decimal mode
+    #[default]
+    Ricoh2A03,
+    // NMOS decimal mode, N and Z do not reflect the BCD result
+    Mos6502,
+    // decimal mode takes an extra cycle on ADC and SBC, with valid N and Z
+    Cmos65C02,
+}
@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+use super::SaveState;
+
+// The last `capacity` save states, one taken every `interval` instructions; once full the oldest is dropped
+pub(super) struct RewindBuffer {
+    states: VecDeque<SaveState>,
+    capacity: usize,
+    interval: u64,
+    instructions: u64,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u64) -> Self {
+        Self { states: VecDeque::with_capacity(capacity), capacity, interval: interval.max(1), instructions: 0 }
+    }
+
+    // counts an instruction, true when a save state is due
+    pub fn tick(&mut self) -> bool {
+        self.instructions += 1;
+        if self.instructions < self.interval {
+            return false;
+        }
+
+        self.instructions = 0;
+        true
+    }
+
+    pub fn push(&mut self, state: SaveState) {
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+
+        self.states.push_back(state);
+    }
+
+    // newest first, the interval restarts from the restored state
+    pub fn pop(&mut self) -> Option<SaveState> {
+        self.instructions = 0;
+        self.states.pop_back()
+    }
+}
@@ -0,0 +1,98 @@
+use std::io;
+use crate::types::Result;
+
+const MAGIC: [u8; 4] = *b"NESS";
+const VERSION: u16 = 1;
+const REGISTERS_LEN: usize = 15;
+// a generous bound on cartridge RAM, far above the 8 KiB a cartridge carries here
+const CARTRIDGE_RAM_LEN_MAX: usize = 0x10_0000;
+
+pub const RAM_LEN: usize = 0x0800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    pub(super) a: u8,
+    pub(super) x: u8,
+    pub(super) y: u8,
+    pub(super) s: u8,
+    pub(super) p: u8,
+    pub(super) pc: u16,
+    pub(super) cycles: u64,
+    pub(super) ram: Vec<u8>,
+    pub(super) cartridge_ram: Vec<u8>,
+}
+
+impl SaveState {
+    // magic, version, then length-prefixed sections for the registers, RAM and cartridge RAM
+    pub fn save_to_writer(&self, writer: &mut impl io::Write) -> Result {
+        let mut registers = Vec::with_capacity(REGISTERS_LEN);
+        registers.extend_from_slice(&[self.a, self.x, self.y, self.s, self.p]);
+        registers.extend_from_slice(&self.pc.to_le_bytes());
+        registers.extend_from_slice(&self.cycles.to_le_bytes());
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        for section in [&registers, &self.ram, &self.cartridge_ram].iter() {
+            writer.write_all(&(section.len() as u32).to_le_bytes())?;
+            writer.write_all(section)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_from_reader(reader: &mut impl io::Read) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(anyhow!("not a save state, magic `{:02X?}` does not match", magic));
+        }
+
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(anyhow!("unsupported save state version `{}`, expected `{}`", version, VERSION));
+        }
+
+        let registers = Self::read_section(reader, "registers", REGISTERS_LEN)?;
+        if registers.len() != REGISTERS_LEN {
+            return Err(anyhow!("expected registers to have length `{}`, received `{}`", REGISTERS_LEN, registers.len()));
+        }
+
+        let ram = Self::read_section(reader, "RAM", RAM_LEN)?;
+        if ram.len() != RAM_LEN {
+            return Err(anyhow!("expected RAM to have length `{}`, received `{}`", RAM_LEN, ram.len()));
+        }
+
+        let cartridge_ram = Self::read_section(reader, "cartridge RAM", CARTRIDGE_RAM_LEN_MAX)?;
+
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&registers[7..15]);
+
+        Ok(Self {
+            a: registers[0],
+            x: registers[1],
+            y: registers[2],
+            s: registers[3],
+            p: registers[4],
+            pc: u16::from_le_bytes([registers[5], registers[6]]),
+            cycles: u64::from_le_bytes(cycles),
+            ram,
+            cartridge_ram,
+        })
+    }
+
+    // the length comes from the file, so it is checked before anything is allocated for it
+    fn read_section(reader: &mut impl io::Read, name: &str, len_max: usize) -> Result<Vec<u8>> {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > len_max {
+            return Err(anyhow!("expected {} to have length at most `{}`, received `{}`", name, len_max, len));
+        }
+
+        let mut section = vec![0; len];
+        reader.read_exact(&mut section)?;
+        Ok(section)
+    }
+}
@@ -0,0 +1,34 @@
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub stop_reason: StopReason,
+}
+
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    // also covers running into the vector table, where there is nothing left to fetch
+    Halted,
+    BreakpointHit(u16),
+    InstructionLimit,
+    // the loop detector saw execution stuck jumping to this PC
+    InfiniteLoop(u16),
+    // the error itself, shared so the summary stays `Clone`; the root cause downcasts to `CpuError` where there is one
+    Error(Rc<anyhow::Error>),
+}
+
+// errors compare by identity, there is no equality on `anyhow::Error`
+impl PartialEq for StopReason {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StopReason::Halted, StopReason::Halted) => true,
+            (StopReason::BreakpointHit(a), StopReason::BreakpointHit(b)) => a == b,
+            (StopReason::InstructionLimit, StopReason::InstructionLimit) => true,
+            (StopReason::InfiniteLoop(a), StopReason::InfiniteLoop(b)) => a == b,
+            (StopReason::Error(a), StopReason::Error(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
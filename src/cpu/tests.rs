@@ -2,11 +2,16 @@
 
 #![cfg(test)]
 
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use super::*;
+use crate::bus::{Access, AccessKind, ClosureMemory, JoypadButton};
+use crate::cartridge::Cartridge;
+use crate::ppu::{self, PpuStub};
+use crate::cartridge::tests::image;
 
 const ADDRESS_PRG: u16 = 0x8000;
 const ADDRESS_IRQ: u16 = 0x5555;
-const INPUT_OPCODE: u8 = 0xFF;
 const INPUT_BYTE: u8 = 0x4F;
 const INPUT_ADDRESS_ZP: u16 = 0x0040;
 const INPUT_ADDRESS: u16 = 0x4020;
@@ -18,6 +23,19 @@ const INPUT_ADDRESS_INDIRECT_HIGH: u8 = INPUT_ADDRESS_INDIRECT.to_le_bytes()[1];
 const OFFSET_REGISTER_X: u8 = 0x12;
 const OFFSET_REGISTER_Y: u8 = 0x24;
 
+// a trace writer target the test can still read after handing it over
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 fn bus() -> Bus {
     let mut bus = Bus::new();
     bus.write_u16(ADDRESS_VECTOR_RESET, ADDRESS_PRG).unwrap();
@@ -30,10 +48,35 @@ fn cpu(bus: Bus) -> Cpu {
     cpu
 }
 
+// loads `bytes` at the reset vector and runs at most `steps_max` instructions
+fn run_program(bytes: &[u8], steps_max: u64) -> Cpu {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, bytes).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.run_for(steps_max).unwrap();
+    cpu
+}
+
+// runs `inspect` with the access log on, it must leave nothing there and open bus where it was
+fn side_effect_free<T>(cpu: &mut Cpu, inspect: impl FnOnce(&Cpu) -> T) -> T {
+    cpu.bus.set_access_log_capacity(16);
+    let open_bus = cpu.bus.open_bus();
+
+    let result = inspect(cpu);
+    assert!(cpu.bus.access_log().is_empty());
+    assert_eq!(cpu.bus.open_bus(), open_bus);
+
+    cpu.bus.set_access_log_capacity(0);
+    result
+}
+
+fn operand(bytes: &[u8]) -> OperandBytes {
+    OperandBytes::new(bytes).unwrap()
+}
+
 fn process_instruction(cpu: &mut Cpu, bytes: &[u8]) {
-    cpu.bus.write_n(cpu.registers.pc, bytes);
-    let instruction = cpu.determine_instruction_next().unwrap();
-    cpu.process_instruction(instruction).unwrap();
+    cpu.bus.write_n(cpu.registers.pc, bytes).unwrap();
+    cpu.run_instruction().unwrap().unwrap();
 }
 
 fn lda_no_flags(cpu: &mut Cpu, value: u8) {
@@ -84,6 +127,21 @@ fn stack_push_pull_u16() {
     assert_eq!(cpu.bus.read(0x01FF), 0);
 }
 
+#[test]
+fn stack_push_u16_wrapping() {
+    let mut cpu = cpu(bus());
+    cpu.registers.s = 0x00;
+
+    cpu.stack_push_u16(0x1234);
+    assert_eq!(cpu.bus.read(0x0100), 0x12);
+    assert_eq!(cpu.bus.read(0x01FF), 0x34);
+    assert_eq!(cpu.bus.read(0x0200), 0x00);
+    assert_eq!(cpu.registers.s, 0xFE);
+
+    assert_eq!(cpu.stack_pull_u16(), 0x1234);
+    assert_eq!(cpu.registers.s, 0x00);
+}
+
 #[test]
 fn stack_overflow() {
     let mut cpu = cpu(bus());
@@ -105,12 +163,197 @@ fn stack_underflow() {
     assert_eq!(cpu.registers.s, 0x00);
 }
 
+#[test]
+fn instruction_len_matches_mode() {
+    let mut official = 0;
+
+    for opcode in 0..=u8::MAX {
+        let instruction = Instruction::decode(opcode);
+        let len_expected = match instruction.mode() {
+            InstructionMode::Implied | InstructionMode::Accumulator => 1,
+            InstructionMode::Immediate
+                | InstructionMode::Relative
+                | InstructionMode::ZeroPage
+                | InstructionMode::ZeroPageX
+                | InstructionMode::ZeroPageY
+                | InstructionMode::IndirectX
+                | InstructionMode::IndirectY
+                => 2,
+            InstructionMode::Absolute
+                | InstructionMode::AbsoluteX
+                | InstructionMode::AbsoluteY
+                | InstructionMode::Indirect
+                => 3,
+        };
+        assert_eq!(instruction.len(), len_expected, "opcode `${:02X}`", opcode);
+
+        if !instruction.illegal() {
+            official += 1;
+        }
+    }
+
+    assert_eq!(official, 151);
+}
+
+#[test]
+fn operand_len() {
+    assert_eq!(InstructionMode::Implied.operand_len(), 0);
+    assert_eq!(InstructionMode::Accumulator.operand_len(), 0);
+    assert_eq!(InstructionMode::Immediate.operand_len(), 1);
+    assert_eq!(InstructionMode::Relative.operand_len(), 1);
+    assert_eq!(InstructionMode::ZeroPage.operand_len(), 1);
+    assert_eq!(InstructionMode::ZeroPageX.operand_len(), 1);
+    assert_eq!(InstructionMode::ZeroPageY.operand_len(), 1);
+    assert_eq!(InstructionMode::IndirectX.operand_len(), 1);
+    assert_eq!(InstructionMode::IndirectY.operand_len(), 1);
+    assert_eq!(InstructionMode::Absolute.operand_len(), 2);
+    assert_eq!(InstructionMode::AbsoluteX.operand_len(), 2);
+    assert_eq!(InstructionMode::AbsoluteY.operand_len(), 2);
+    assert_eq!(InstructionMode::Indirect.operand_len(), 2);
+
+    for opcode in 0..=u8::MAX {
+        let instruction = Instruction::decode(opcode);
+        assert_eq!(instruction.len(), 1 + instruction.mode().operand_len(), "opcode `${:02X}`", opcode);
+    }
+}
+
+#[test]
+fn decode_stream() {
+    // LDA #$42, STA $0200, ASL A, BNE -4, then a JMP cut short
+    let instructions = instruction::decode_stream(&[0xA9, 0x42, 0x8D, 0x00, 0x02, 0x0A, 0xD0, 0xFC, 0x4C, 0x00]);
+    assert_eq!(instructions, vec![
+        Instruction::decode(0xA9),
+        Instruction::decode(0x8D),
+        Instruction::decode(0x0A),
+        Instruction::decode(0xD0),
+    ]);
+    assert_eq!(instructions[1].operation(), InstructionOperation::Sta);
+    assert_eq!(instructions[1].mode(), InstructionMode::Absolute);
+
+    assert!(instruction::decode_stream(&[]).is_empty());
+}
+
+#[test]
+fn operation_mnemonics() {
+    assert_eq!(InstructionOperation::Adc.mnemonic(), "ADC");
+    assert_eq!(InstructionOperation::Jsr.mnemonic(), "JSR");
+    assert_eq!(InstructionOperation::Txs.mnemonic(), "TXS");
+    assert_eq!(InstructionOperation::Lax.mnemonic(), "LAX");
+    assert_eq!(InstructionOperation::Xaa.mnemonic(), "XAA");
+
+    // every operation is listed once, and every decoded opcode is among them
+    let all: HashSet<_> = InstructionOperation::ALL.iter().copied().collect();
+    assert_eq!(all.len(), InstructionOperation::ALL.len());
+    assert_eq!(all.len(), 75);
+    assert!((0..=u8::MAX).all(|opcode| all.contains(&Instruction::decode(opcode).operation())));
+
+    let mnemonics: HashSet<_> = InstructionOperation::ALL.iter().map(|operation| operation.mnemonic()).collect();
+    assert_eq!(mnemonics.len(), 75);
+}
+
+#[test]
+fn fetch_decode_execute() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0x69, 0x20, 0x4C, 0x34, 0x12]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.a = 0x10;
+
+    let (opcode, address) = cpu.fetch();
+    assert_eq!((opcode, address), (0x69, ADDRESS_PRG));
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+
+    let instruction = Instruction::decode(opcode);
+    assert_eq!(instruction.operation(), InstructionOperation::Adc);
+    assert_eq!(instruction.mode(), InstructionMode::Immediate);
+
+    let operand = cpu.fetch_operand(instruction);
+    assert_eq!(operand.as_slice(), &[0x20]);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+
+    assert_eq!(cpu.execute(instruction, operand).unwrap().cycles(), 2);
+    assert_eq!(cpu.registers.a, 0x30);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+
+    // execute only needs PC past the instruction, whatever fetched it
+    let (opcode, _) = cpu.fetch();
+    let instruction = Instruction::decode(opcode);
+    let operand = cpu.fetch_operand(instruction);
+    assert_eq!(operand.word().unwrap(), 0x1234);
+    cpu.execute(instruction, operand).unwrap();
+    assert_eq!(cpu.registers.pc, 0x1234);
+}
+
+#[test]
+fn decode_opcode() {
+    let instruction = Instruction::decode(0x69);
+    assert_eq!(instruction.opcode(), 0x69);
+    assert_eq!(instruction.operation(), InstructionOperation::Adc);
+    assert_eq!(instruction.mode(), InstructionMode::Immediate);
+    assert_eq!((instruction.len(), instruction.cycles_base()), (2, 2));
+    assert!(!instruction.illegal());
+}
+
+#[test]
+fn fetch_operand_implied() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0x0A]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.bus.set_access_log_capacity(8);
+    cpu.registers.a = 0x21;
+
+    // neither INX nor ASL A reads past its opcode
+    for _ in 0..2 {
+        let (opcode, _) = cpu.fetch();
+        let instruction = Instruction::decode(opcode);
+        let operand = cpu.fetch_operand(instruction);
+        assert!(operand.as_slice().is_empty());
+        assert!(operand.get(0).is_err());
+        cpu.execute(instruction, operand).unwrap();
+    }
+
+    assert_eq!((cpu.registers.x, cpu.registers.a), (0x01, 0x42));
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+    assert_eq!(cpu.bus.access_log().len(), 2);
+}
+
+#[test]
+fn opcode_tables_match_decode() {
+    for opcode in 0..=u8::MAX {
+        let decoded = Instruction::table_entry(opcode);
+        let instruction = Instruction::decode(opcode);
+        let index = opcode as usize;
+
+        assert_eq!(instruction::OPCODE_OP[index], decoded.operation(), "opcode `${:02X}`", opcode);
+        assert_eq!(instruction::OPCODE_MODE[index], decoded.mode(), "opcode `${:02X}`", opcode);
+        assert_eq!(instruction::OPCODE_LEN[index], decoded.len(), "opcode `${:02X}`", opcode);
+        assert_eq!(instruction::OPCODE_CYCLES[index], decoded.cycles_base(), "opcode `${:02X}`", opcode);
+        assert_eq!(instruction.illegal(), decoded.illegal(), "opcode `${:02X}`", opcode);
+        assert_eq!(instruction.operation(), decoded.operation(), "opcode `${:02X}`", opcode);
+    }
+}
+
+#[test]
+fn pc_desynchronized() {
+    let mut cpu = cpu(bus());
+    cpu.registers.pc = ADDRESS_PRG + 3;
+    let instruction = Instruction::new(InstructionOperation::Lda, InstructionMode::Immediate, 2).with_len(3);
+
+    let error = cpu.execute(instruction, operand(&[0x42])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::PcDesynchronized {
+        operation: InstructionOperation::Lda,
+        address: ADDRESS_PRG,
+        pc: ADDRESS_PRG + 3,
+    }));
+    assert_eq!(error.to_string(), "PC desynchronized after `LDA` at `$8000`: moved to `$8003`");
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG);
+}
+
 #[test]
 fn determine_input_implied() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Implied,
-        &[INPUT_OPCODE],
+        &operand(&[]),
     ).unwrap();
     assert_eq!(input, InstructionInput::Implied);
 }
@@ -120,7 +363,7 @@ fn determine_input_accumulator() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Accumulator,
-        &[INPUT_OPCODE],
+        &operand(&[]),
     ).unwrap();
     assert_eq!(input, InstructionInput::Location(InstructionInputLocation::Accumulator));
 }
@@ -131,7 +374,7 @@ fn determine_input_relative_positive() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Relative,
-        &[INPUT_OPCODE, 0x0F],
+        &operand(&[0x0F]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(0x800F));
 }
@@ -141,7 +384,7 @@ fn determine_input_zero_page() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::ZeroPage,
-        &[INPUT_OPCODE, INPUT_ADDRESS_ZP as u8],
+        &operand(&[INPUT_ADDRESS_ZP as u8]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS_ZP));
 }
@@ -153,7 +396,7 @@ fn determine_input_zero_page_x() {
 
     let input = cpu.determine_input(
         InstructionMode::ZeroPageX,
-        &[INPUT_OPCODE, INPUT_ADDRESS_ZP as u8],
+        &operand(&[INPUT_ADDRESS_ZP as u8]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS_ZP + OFFSET_REGISTER_X as u16));
 }
@@ -165,7 +408,7 @@ fn determine_input_zero_page_y() {
 
     let input = cpu.determine_input(
         InstructionMode::ZeroPageY,
-        &[INPUT_OPCODE, INPUT_ADDRESS_ZP as u8],
+        &operand(&[INPUT_ADDRESS_ZP as u8]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS_ZP + OFFSET_REGISTER_Y as u16));
 }
@@ -176,7 +419,7 @@ fn determine_input_relative_negative() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Relative,
-        &[INPUT_OPCODE, 0xF0],
+        &operand(&[0xF0]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(0x7FF0));
 }
@@ -186,7 +429,7 @@ fn determine_input_immediate() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Immediate,
-        &[INPUT_OPCODE, INPUT_BYTE],
+        &operand(&[INPUT_BYTE]),
     ).unwrap();
     assert_eq!(input, InstructionInput::Byte(INPUT_BYTE));
 }
@@ -196,7 +439,7 @@ fn determine_input_absolute() {
     let cpu = cpu(bus());
     let input = cpu.determine_input(
         InstructionMode::Absolute,
-        &[INPUT_OPCODE, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH],
+        &operand(&[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS));
 }
@@ -208,7 +451,7 @@ fn determine_input_absolute_x() {
 
     let input = cpu.determine_input(
         InstructionMode::AbsoluteX,
-        &[INPUT_OPCODE, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH],
+        &operand(&[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS + OFFSET_REGISTER_X as u16));
 }
@@ -220,7 +463,7 @@ fn determine_input_absolute_y() {
 
     let input = cpu.determine_input(
         InstructionMode::AbsoluteY,
-        &[INPUT_OPCODE, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH],
+        &operand(&[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS + OFFSET_REGISTER_Y as u16));
 }
@@ -233,7 +476,7 @@ fn determine_input_indirect() {
     let cpu = cpu(bus);
     let input = cpu.determine_input(
         InstructionMode::Indirect,
-        &[INPUT_OPCODE, INPUT_ADDRESS_INDIRECT_LOW, INPUT_ADDRESS_INDIRECT_HIGH],
+        &operand(&[INPUT_ADDRESS_INDIRECT_LOW, INPUT_ADDRESS_INDIRECT_HIGH]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS));
 }
@@ -248,7 +491,24 @@ fn determine_input_indirect_x() {
 
     let input = cpu.determine_input(
         InstructionMode::IndirectX,
-        &[INPUT_OPCODE, INPUT_ADDRESS_ZP as u8],
+        &operand(&[INPUT_ADDRESS_ZP as u8]),
+    ).unwrap();
+    assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS));
+}
+
+#[test]
+fn determine_input_indirect_x_wrapping() {
+    let mut bus = bus();
+    bus.write(0x00FF, INPUT_ADDRESS_LOW);
+    bus.write(0x0000, INPUT_ADDRESS_HIGH);
+    bus.write(0x0100, 0xFF);
+
+    let mut cpu = cpu(bus);
+    cpu.registers.x = 0x01;
+
+    let input = cpu.determine_input(
+        InstructionMode::IndirectX,
+        &operand(&[0xFE]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS));
 }
@@ -263,11 +523,34 @@ fn determine_input_indirect_y() {
 
     let input = cpu.determine_input(
         InstructionMode::IndirectY,
-        &[INPUT_OPCODE, INPUT_ADDRESS_ZP as u8],
+        &operand(&[INPUT_ADDRESS_ZP as u8]),
     ).unwrap();
     assert_eq!(input, InstructionInput::from_address(INPUT_ADDRESS + OFFSET_REGISTER_Y as u16));
 }
 
+#[test]
+fn determine_input_indirect_y_wrapping() {
+    let mut bus = bus();
+    bus.write(0x00FF, 0xF0);
+    bus.write(0x0000, 0x12);
+    bus.write(0x0100, 0xFF);
+
+    let mut cpu = cpu(bus);
+    cpu.registers.y = OFFSET_REGISTER_Y;
+
+    // the pointer wraps within zero page, adding Y then carries into the high byte
+    let input = cpu.determine_input(InstructionMode::IndirectY, &operand(&[0xFF])).unwrap();
+    assert_eq!(input, InstructionInput::from_address(0x12F0 + OFFSET_REGISTER_Y as u16));
+
+    cpu.bus.write(0x0000, 0xFF);
+    let input = cpu.determine_input(InstructionMode::IndirectY, &operand(&[0xFF])).unwrap();
+    assert_eq!(input, InstructionInput::from_address(0x0014));
+
+    cpu.bus.write(0x0014, INPUT_BYTE);
+    process_instruction(&mut cpu, &[0xB1, 0xFF]);
+    assert_eq!(cpu.registers.a, INPUT_BYTE);
+}
+
 #[test]
 fn process_adc_absolute() {
     let mut cpu = cpu(bus());
@@ -334,6 +617,53 @@ fn process_bcc_relative() {
     assert_eq!(cpu.registers.pc, 0x7FF2);
 }
 
+#[test]
+fn branch_cycles() {
+    // BCC taken with C clear, not taken with C set
+    let cases = [
+        (ADDRESS_PRG, 0x10, false, 2 + 1),
+        (0x80F0, 0x20, false, 2 + 2),
+        (ADDRESS_PRG, 0xF0, false, 2 + 2),
+        // the branch itself spans $80FE-$80FF, the target shares a page with the instruction after it
+        (0x80FE, 0x10, false, 2 + 1),
+        (0x80F0, 0x20, true, 2),
+    ];
+
+    for &(address, offset, carry, cycles) in &cases {
+        let mut cpu = cpu(bus());
+        cpu.registers.pc = address;
+        cpu.registers.p.set(StatusFlags::CARRY, carry);
+
+        process_instruction(&mut cpu, &[0x90, offset]);
+        assert_eq!(cpu.cycle_count(), cycles, "BCC ${:02X} at ${:04X}", offset, address);
+    }
+}
+
+#[test]
+fn absolute_end_of_address_space() {
+    let mut cpu = cpu(bus());
+    cpu.bus.write(0xFFFF, INPUT_BYTE);
+    cpu.bus.write(0x0000, 0x01);
+
+    process_instruction(&mut cpu, &[0xAD, 0xFF, 0xFF]);
+    assert_eq!(cpu.registers.a, INPUT_BYTE);
+
+    process_instruction(&mut cpu, &[0xEE, 0xFF, 0xFF]);
+    assert_eq!(cpu.bus.read(0xFFFF), INPUT_BYTE + 1);
+
+    // LDA $FFF0,X lands on $FFFF, then wraps round to $0000
+    cpu.registers.x = 0x0F;
+    process_instruction(&mut cpu, &[0xBD, 0xF0, 0xFF]);
+    assert_eq!(cpu.registers.a, INPUT_BYTE + 1);
+    cpu.registers.x = 0x10;
+    process_instruction(&mut cpu, &[0xBD, 0xF0, 0xFF]);
+    assert_eq!(cpu.registers.a, 0x01);
+
+    cpu.registers.a = 0x42;
+    process_instruction(&mut cpu, &[0x8D, 0xFF, 0xFF]);
+    assert_eq!(cpu.bus.read(0xFFFF), 0x42);
+}
+
 #[test]
 fn process_bcs_relative() {
     let mut cpu = cpu(bus());
@@ -408,18 +738,259 @@ fn process_brk_implied() {
     bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
 
     let mut cpu = cpu(bus);
-    cpu.registers.p = StatusFlags::INTERRUPT_DISABLE | StatusFlags::ZERO;
-
-    process_instruction(&mut cpu, &[0x00]);
-    assert_eq!(cpu.registers.pc, 0x8001);
-    cpu.registers.p.remove(StatusFlags::INTERRUPT_DISABLE);
+    cpu.registers.p = StatusFlags::ZERO;
 
     let pc_old = cpu.registers.pc;
     process_instruction(&mut cpu, &[0x00]);
-    assert_eq!(cpu.stack_pull(), StatusFlags::ZERO.bits());
+    assert_eq!(cpu.stack_pull(), 0x32);
     assert_eq!(cpu.stack_pull_u16(), pc_old);
     assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
-    assert!(cpu.registers.p.contains(StatusFlags::BREAK_LEFT | StatusFlags::BREAK_RIGHT));
+    assert_eq!(cpu.registers.p, StatusFlags::ZERO | StatusFlags::INTERRUPT_DISABLE);
+
+    // not masked by I
+    cpu.registers.pc = ADDRESS_PRG;
+    process_instruction(&mut cpu, &[0x00]);
+    assert_eq!(cpu.stack_pull(), 0x36);
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+}
+
+#[test]
+fn process_brk_nmi_hijack() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    bus.write_u16(ADDRESS_VECTOR_NMI, 0x9000).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0x00]).unwrap();
+
+    let mut cpu = cpu(bus);
+    cpu.registers.p = StatusFlags::ZERO;
+    cpu.trigger_nmi();
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.pc, 0x9000);
+    // still flagged as BRK, ZERO with both break bits
+    assert_eq!(cpu.stack_pull(), 0x32);
+    assert_eq!(cpu.stack_pull_u16(), ADDRESS_PRG);
+    assert_eq!(cpu.interrupt_cycle_count(), 7);
+
+    // nor does I keep the hijack from happening
+    cpu.registers.pc = ADDRESS_PRG;
+    cpu.registers.p = StatusFlags::INTERRUPT_DISABLE;
+    cpu.trigger_nmi();
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.pc, 0x9000);
+    assert_eq!(cpu.stack_pull(), 0x34);
+}
+
+#[test]
+fn process_brk_break_halts() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    // INX; BRK; INX
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0x00, 0xE8]).unwrap();
+
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_break_halts(true);
+    let s_old = cpu.registers.s;
+
+    assert_eq!(cpu.start(), RunSummary { instructions: 2, cycles: 2 + 7, stop_reason: StopReason::Halted });
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+    assert_eq!(cpu.registers.x, 0x01);
+    assert_eq!(cpu.registers.s, s_old);
+}
+
+#[test]
+fn start_instruction_limit() {
+    let mut bus = bus();
+    // NOP; JMP $8001
+    bus.write_n(ADDRESS_PRG, &[0xEA, 0x4C, 0x01, 0x80]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_instruction_limit(Some(10));
+
+    assert_eq!(cpu.start(), RunSummary { instructions: 10, cycles: 2 + 9 * 3, stop_reason: StopReason::InstructionLimit });
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+}
+
+#[test]
+fn run_headless_matches_start() {
+    // LDX #$00; INX; STX $10; BNE -5; LDA $10; JMP $8000
+    let program = [0xA2, 0x00, 0xE8, 0x86, 0x10, 0xD0, 0xFB, 0xA5, 0x10, 0x4C, 0x00, 0x80];
+    let machine = || {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, &program).unwrap();
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_hash_execution(true).set_detect_self_modifying_code(true);
+        cpu
+    };
+
+    let mut cpu_started = machine();
+    cpu_started.options_mut().set_instruction_limit(Some(1000));
+    cpu_started.start();
+
+    let mut cpu_headless = machine();
+    assert_eq!(cpu_headless.run_headless(1000).unwrap(), 1000);
+    assert_eq!(cpu_headless.save_state(), cpu_started.save_state());
+    assert_eq!(cpu_headless.execution_hash(), FNV_OFFSET_BASIS);
+    assert!(cpu_headless.options().hash_execution());
+}
+
+#[test]
+fn run_headless_uninstrumented() {
+    let mut bus = bus();
+    // INX; JMP $8001
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0x4C, 0x01, 0x80]).unwrap();
+    bus.set_access_log_capacity(16);
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_loop_detection(Some(3));
+    cpu.set_rewind(4, 1);
+    let buffer = Rc::new(RefCell::new(vec![]));
+    cpu.attach_trace_writer(Box::new(SharedBuffer(buffer.clone())));
+    let access_log = cpu.bus.access_log();
+    #[cfg(feature = "stats")]
+    let read_count = cpu.bus.read_count();
+
+    assert_eq!(cpu.run_headless(10).unwrap(), 10);
+    assert_eq!(cpu.bus.access_log(), access_log);
+    #[cfg(feature = "stats")]
+    assert_eq!(cpu.bus.read_count(), read_count);
+    assert!(cpu.loop_landings.is_empty());
+    assert_eq!(cpu.loop_detected, None);
+    assert!(!cpu.rewind().unwrap());
+    cpu.detach_trace_writer().unwrap();
+    assert!(buffer.borrow().is_empty());
+    // all back on afterwards, for one JMP $8001
+    cpu.attach_trace_writer(Box::new(SharedBuffer(buffer.clone())));
+    cpu.run_for(1).unwrap();
+    cpu.detach_trace_writer().unwrap();
+    assert_eq!(cpu.bus.access_log().len(), access_log.len() + 3);
+    assert!(!buffer.borrow().is_empty());
+    assert_eq!(cpu.options().loop_detection(), Some(3));
+    assert!(cpu.rewind().unwrap());
+}
+
+#[test]
+fn cycle_table() {
+    let mut bus = bus();
+    // NOP; LDA $80F0,X with X = $20
+    bus.write_n(ADDRESS_PRG, &[0xEA, 0xBD, 0xF0, 0x80]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.x = 0x20;
+
+    let mut table = cpu.cycle_table();
+    assert_eq!((table[0xEA], table[0xBD]), (2, 4));
+    table[0xEA] = 3;
+    table[0xBD] = 5;
+    cpu.set_cycle_table(Some(table));
+    assert_eq!(cpu.cycle_table()[0xEA], 3);
+
+    // the page crossing penalty still applies on top
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.cycle_count(), 3 + 5 + 1);
+
+    cpu.set_cycle_table(None);
+    cpu.registers.pc = ADDRESS_PRG;
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycle_count(), 3 + 5 + 1 + 2);
+}
+
+#[test]
+fn start_infinite_loop() {
+    let run = |program: &[u8]| {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, program).unwrap();
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_loop_detection(Some(3)).set_instruction_limit(Some(100));
+        cpu.start().stop_reason
+    };
+
+    // INX; JMP $8001
+    assert_eq!(run(&[0xE8, 0x4C, 0x01, 0x80]), StopReason::InfiniteLoop(0x8001));
+    // INX; JMP $8000, busy rather than stuck
+    assert_eq!(run(&[0xE8, 0x4C, 0x00, 0x80]), StopReason::InstructionLimit);
+    // STA $00; JMP $8000, the same registers each time but memory is being written
+    assert_eq!(run(&[0x85, 0x00, 0x4C, 0x00, 0x80]), StopReason::InstructionLimit);
+}
+
+#[test]
+fn loop_detection_cleared() {
+    let mut bus = bus();
+    // INX; JMP $8001
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0x4C, 0x01, 0x80]).unwrap();
+    let mut cpu = cpu(bus);
+    // INX and two landings on the JMP, one short of detection
+    cpu.options_mut().set_loop_detection(Some(3)).set_instruction_limit(Some(3));
+    let state = cpu.save_state();
+
+    cpu.run_for(5).unwrap();
+    assert_eq!(cpu.loop_detected, Some(0x8001));
+
+    // X is 1 again by the first landing, which would otherwise carry on from the old count
+    cpu.load_state(&state).unwrap();
+    assert!(cpu.loop_landings.is_empty());
+    assert_eq!(cpu.loop_detected, None);
+    assert_eq!(cpu.start().stop_reason, StopReason::InstructionLimit);
+}
+
+#[test]
+fn loop_detection_waiting_for_nmi() {
+    let mut bus = bus();
+    // forever: JMP forever; at the NMI vector: INX, RTI
+    bus.write_n(ADDRESS_PRG, &[0x4C, 0x00, 0x80]).unwrap();
+    bus.write_n(ADDRESS_IRQ, &[0xE8, 0x40]).unwrap();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_loop_detection(Some(3)).set_instruction_limit(Some(10));
+
+    // seven JMPs, the NMI handler and one more JMP, all waiting
+    cpu.schedule_nmi(20);
+    assert_eq!(cpu.start().stop_reason, StopReason::InstructionLimit);
+    assert_eq!(cpu.registers.x, 0x01);
+
+    // with nothing left to wake it the same loop is stuck
+    assert_eq!(cpu.start().stop_reason, StopReason::InfiniteLoop(ADDRESS_PRG));
+
+    // an NMI already pending when the loop would be reported is serviced instead
+    cpu.trigger_nmi();
+    assert_eq!(cpu.start().stop_reason, StopReason::InfiniteLoop(ADDRESS_PRG));
+    assert_eq!(cpu.registers.x, 0x02);
+}
+
+#[test]
+fn loop_detection_polling_device() {
+    let run = |program: &[u8]| {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, program).unwrap();
+        // vblank far off
+        bus.register_handler(ppu::ADDRESS_RANGE, Rc::new(RefCell::new(PpuStub::new(100_000))));
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_loop_detection(Some(3)).set_instruction_limit(Some(100));
+        cpu.start().stop_reason
+    };
+
+    // wait: LDA $2002; BPL wait
+    assert_eq!(run(&[0xAD, 0x02, 0x20, 0x10, 0xFB]), StopReason::InstructionLimit);
+    // wait: LDA $4016; AND #$01; BEQ wait
+    assert_eq!(run(&[0xAD, 0x16, 0x40, 0x29, 0x01, 0xF0, 0xF9]), StopReason::InstructionLimit);
+    // wait: LDA $0200; BPL wait, polling plain RAM is stuck
+    assert_eq!(run(&[0xAD, 0x00, 0x02, 0x10, 0xFB]), StopReason::InfiniteLoop(ADDRESS_PRG));
+}
+
+#[test]
+fn start_breakpoint() {
+    let mut bus = bus();
+    // INX; INX; INX
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0xE8, 0xE8]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.add_breakpoint(ADDRESS_PRG + 2);
+    cpu.options_mut().set_instruction_limit(Some(3));
+
+    assert_eq!(cpu.start().stop_reason, StopReason::BreakpointHit(ADDRESS_PRG + 2));
+    assert_eq!(cpu.registers.x, 0x02);
+
+    cpu.remove_breakpoint(ADDRESS_PRG + 2);
+    cpu.registers.pc = ADDRESS_PRG;
+    assert_eq!(cpu.start().stop_reason, StopReason::InstructionLimit);
+    assert_eq!(cpu.registers.x, 0x05);
 }
 
 #[test]
@@ -680,6 +1251,110 @@ fn process_jmp_absolute() {
     assert_eq!(cpu.registers.pc, INPUT_ADDRESS);
 }
 
+#[test]
+fn process_jmp_absolute_little_endian() {
+    let mut cpu = cpu(bus());
+    process_instruction(&mut cpu, &[0x4C, 0x34, 0x12]);
+    assert_eq!(cpu.registers.pc, 0x1234);
+}
+
+#[test]
+fn survey_unimplemented() {
+    // XAA #$00, LAS $1000,Y, XAA #$00, LDA #$42, JAM
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0x8B, 0x00, 0xBB, 0x00, 0x10, 0x8B, 0x00, 0xA9, 0x42, 0x02]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_survey_unimplemented(true);
+
+    cpu.run_for(10).unwrap();
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(cpu.missing_opcodes().iter().copied().collect::<Vec<_>>(), vec![0x8B, 0xBB]);
+}
+
+#[test]
+fn unimplemented_operation() {
+    let mut bus = bus();
+    // XAA #$00
+    bus.write_n(ADDRESS_PRG, &[0x8B, 0x00]).unwrap();
+    let mut cpu = cpu(bus);
+
+    let error = cpu.run_instruction().unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::ExecutionFailed {
+        pc: ADDRESS_PRG,
+        opcode: 0x8B,
+        mode: InstructionMode::Immediate,
+        operand: vec![0x00],
+    }));
+    assert_eq!(error.root_cause().downcast_ref::<CpuError>(), Some(&CpuError::UnimplementedOperation {
+        operation: InstructionOperation::Xaa,
+        reason: "is not implemented",
+    }));
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG);
+}
+
+#[test]
+fn start_error() {
+    let mut bus = bus();
+    // INX; XAA #$00
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0x8B, 0x00]).unwrap();
+    let mut cpu = cpu(bus);
+
+    let summary = cpu.start();
+    assert_eq!(summary.instructions, 1);
+    let error = match summary.stop_reason {
+        StopReason::Error(error) => error,
+        stop_reason => panic!("expected an error, stopped with `{:?}`", stop_reason),
+    };
+    assert!(matches!(error.downcast_ref::<CpuError>(), Some(CpuError::ExecutionFailed { pc: 0x8001, .. })));
+    assert_eq!(error.root_cause().downcast_ref::<CpuError>(), Some(&CpuError::UnimplementedOperation {
+        operation: InstructionOperation::Xaa,
+        reason: "is not implemented",
+    }));
+}
+
+#[test]
+fn process_jmp_to_self_halts() {
+    // INX; JMP $8001
+    let program = [0xE8, 0x4C, 0x01, 0x80];
+
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &program).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_jump_to_self_halts(true);
+
+    assert_eq!(cpu.run_for(100).unwrap(), 2);
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+    assert_eq!(cpu.registers.x, 0x01);
+
+    let cpu = run_program(&program, 100);
+    assert_eq!(cpu.status(), CpuStatus::Running);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+}
+
+#[test]
+fn process_jmp_indirect() {
+    let mut bus = bus();
+    bus.write_u16(INPUT_ADDRESS_INDIRECT, INPUT_ADDRESS).unwrap();
+    let mut cpu = cpu(bus);
+
+    process_instruction(&mut cpu, &[0x6C, INPUT_ADDRESS_INDIRECT_LOW, INPUT_ADDRESS_INDIRECT_HIGH]);
+    assert_eq!(cpu.registers.pc, INPUT_ADDRESS);
+}
+
+#[test]
+fn process_jmp_indirect_page_boundary() {
+    let mut bus = bus();
+    bus.write(0x02FF, INPUT_ADDRESS_LOW);
+    bus.write(0x0200, INPUT_ADDRESS_HIGH);
+    bus.write(0x0300, 0xFF);
+    let mut cpu = cpu(bus);
+
+    process_instruction(&mut cpu, &[0x6C, 0xFF, 0x02]);
+    assert_eq!(cpu.registers.pc, INPUT_ADDRESS);
+}
+
 #[test]
 fn process_jsr_absolute() {
     let mut cpu = cpu(bus());
@@ -828,40 +1503,162 @@ fn process_plp_implied() {
 
     cpu.registers.p = StatusFlags::empty();
     process_instruction(&mut cpu, &[0x28]);
-    assert_eq!(cpu.registers.p, flags);
+    assert_eq!(cpu.registers.p, flags | StatusFlags::BREAK_LEFT);
 }
 
 #[test]
-fn process_rol_accumulator() {
+fn effective_address() {
     let mut cpu = cpu(bus());
-    lda_no_flags(&mut cpu, 0b1010_0000);
-
-    process_instruction(&mut cpu, &[0x2A]);
-    assert_eq!(cpu.registers.a, 0b0100_0000);
-    assert_eq!(cpu.registers.p, StatusFlags::CARRY);
+    cpu.registers.x = OFFSET_REGISTER_X;
 
-    process_instruction(&mut cpu, &[0x2A]);
-    assert_eq!(cpu.registers.a, 0b1000_0001);
-    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
+    let sta = Instruction::decode(0x9D);
+    let address = cpu.effective_address(&sta, &[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]).unwrap();
+    assert_eq!(address, Some(INPUT_ADDRESS + OFFSET_REGISTER_X as u16));
+    assert_eq!(cpu.bus.read(INPUT_ADDRESS + OFFSET_REGISTER_X as u16), 0x00);
 
-    lda_no_flags(&mut cpu, 0b1000_0000);
-    process_instruction(&mut cpu, &[0x2A]);
-    assert_eq!(cpu.registers.a, 0x00);
-    assert_eq!(cpu.registers.p, StatusFlags::ZERO | StatusFlags::CARRY);
+    assert_eq!(cpu.effective_address(&Instruction::decode(0xA9), &[INPUT_BYTE]).unwrap(), None);
+    assert_eq!(cpu.effective_address(&Instruction::decode(0x0A), &[]).unwrap(), None);
+    assert_eq!(cpu.effective_address(&Instruction::decode(0xE8), &[]).unwrap(), None);
+    assert!(cpu.effective_address(&sta, &[INPUT_ADDRESS_LOW]).is_err());
 }
 
 #[test]
-fn process_ror_accumulator() {
-    let mut cpu = cpu(bus());
-    lda_no_flags(&mut cpu, 0x01);
+fn adc_sbc_read_only() {
+    // ADC $0200, then SBC ($40),Y which lands on the same byte
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0x6D, 0x00, 0x02, 0xF1, INPUT_ADDRESS_ZP as u8]).unwrap();
+    bus.write_n(INPUT_ADDRESS_ZP, &[0xF0, 0x01]).unwrap();
+    bus.write(0x0200, INPUT_BYTE);
+    let mut cpu = cpu(bus);
+    cpu.registers.y = 0x10;
 
-    process_instruction(&mut cpu, &[0x6A]);
-    assert_eq!(cpu.registers.a, 0x00);
-    assert_eq!(cpu.registers.p, StatusFlags::ZERO | StatusFlags::CARRY);
+    for _ in 0..2 {
+        cpu.bus.set_access_log_capacity(8);
+        cpu.run_instruction().unwrap().unwrap();
 
-    process_instruction(&mut cpu, &[0x6A]);
-    assert_eq!(cpu.registers.a, 0x80);
-    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
+        let log = cpu.bus.access_log();
+        assert!(log.contains(&Access { kind: AccessKind::Read, address: 0x0200, value: INPUT_BYTE }));
+        assert!(log.iter().all(|access| access.kind != AccessKind::Write));
+    }
+
+    assert_eq!(cpu.bus.read(0x0200), INPUT_BYTE);
+}
+
+#[test]
+fn instruction_bytes() {
+    let mut bus = bus();
+    // INX; LDA $1234
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0xAD, 0x34, 0x12, 0xFF]).unwrap();
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.instruction_bytes(ADDRESS_PRG), vec![0xE8]);
+    assert_eq!(cpu.instruction_bytes(ADDRESS_PRG + 1), vec![0xAD, 0x34, 0x12]);
+}
+
+#[test]
+fn instruction_bytes_side_effect_free() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xAD, 0x34, 0x12]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.bus.write(0x0200, 0x5A);
+
+    assert_eq!(side_effect_free(&mut cpu, |cpu| cpu.instruction_bytes(ADDRESS_PRG)), vec![0xAD, 0x34, 0x12]);
+    assert_eq!(side_effect_free(&mut cpu, |cpu| cpu.trace_state().bytes), vec![0xAD, 0x34, 0x12]);
+}
+
+#[test]
+fn preview_operand_value() {
+    let mut cpu = cpu(bus());
+    cpu.bus.write(INPUT_ADDRESS, INPUT_BYTE);
+
+    let lda = Instruction::decode(0xAD);
+    let value = cpu.preview_operand_value(&lda, &[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]).unwrap();
+    assert_eq!(value, Some(INPUT_BYTE));
+    assert_eq!(cpu.registers.a, 0x00);
+
+    process_instruction(&mut cpu, &[0xAD, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]);
+    assert_eq!(Some(cpu.registers.a), value);
+
+    assert_eq!(cpu.preview_operand_value(&Instruction::decode(0xA9), &[INPUT_BYTE]).unwrap(), Some(INPUT_BYTE));
+    assert_eq!(cpu.preview_operand_value(&Instruction::decode(0x8D), &[INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]).unwrap(), None);
+    assert_eq!(cpu.preview_operand_value(&Instruction::decode(0xE8), &[]).unwrap(), None);
+}
+
+#[test]
+fn preview_operand_value_side_effect_free() {
+    let mut bus = bus();
+    bus.register_handler(ppu::ADDRESS_RANGE, Rc::new(RefCell::new(PpuStub::new(1))));
+    bus.tick(1);
+    bus.controller_mut().press(JoypadButton::A);
+    bus.write(0x4016, 0x01);
+    bus.write(0x4016, 0x00);
+    let mut cpu = cpu(bus);
+
+    let lda = Instruction::decode(0xAD);
+    for _ in 0..2 {
+        let preview = |cpu: &mut Cpu, operand: &[u8]| {
+            side_effect_free(cpu, |cpu| cpu.preview_operand_value(&lda, operand).unwrap())
+        };
+        assert_eq!(preview(&mut cpu, &[0x02, 0x20]), Some(0x80));
+        assert_eq!(preview(&mut cpu, &[0x16, 0x40]), Some(0x01));
+    }
+
+    // the vblank bit and the A button are still there for the real loads
+    process_instruction(&mut cpu, &[0xAD, 0x02, 0x20]);
+    assert_eq!(cpu.registers.a, 0x80);
+    process_instruction(&mut cpu, &[0xAD, 0x16, 0x40]);
+    assert_eq!(cpu.registers.a, 0x01);
+}
+
+#[test]
+fn process_plp_break_flags() {
+    let mut cpu = cpu(bus());
+    cpu.stack_push((StatusFlags::NEGATIVE | StatusFlags::CARRY).bits());
+
+    cpu.registers.p = StatusFlags::OVERFLOW | StatusFlags::ZERO | StatusFlags::BREAK_RIGHT;
+    process_instruction(&mut cpu, &[0x28]);
+    assert_eq!(
+        cpu.registers.p,
+        StatusFlags::NEGATIVE | StatusFlags::CARRY | StatusFlags::BREAK_LEFT | StatusFlags::BREAK_RIGHT,
+    );
+
+    cpu.stack_push(StatusFlags::all().bits());
+    cpu.registers.p = StatusFlags::empty();
+    process_instruction(&mut cpu, &[0x28]);
+    assert_eq!(cpu.registers.p, StatusFlags::all() - StatusFlags::BREAK_RIGHT);
+}
+
+#[test]
+fn process_rol_accumulator() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0b1010_0000);
+
+    process_instruction(&mut cpu, &[0x2A]);
+    assert_eq!(cpu.registers.a, 0b0100_0000);
+    assert_eq!(cpu.registers.p, StatusFlags::CARRY);
+
+    process_instruction(&mut cpu, &[0x2A]);
+    assert_eq!(cpu.registers.a, 0b1000_0001);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
+
+    lda_no_flags(&mut cpu, 0b1000_0000);
+    process_instruction(&mut cpu, &[0x2A]);
+    assert_eq!(cpu.registers.a, 0x00);
+    assert_eq!(cpu.registers.p, StatusFlags::ZERO | StatusFlags::CARRY);
+}
+
+#[test]
+fn process_ror_accumulator() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0x01);
+
+    process_instruction(&mut cpu, &[0x6A]);
+    assert_eq!(cpu.registers.a, 0x00);
+    assert_eq!(cpu.registers.p, StatusFlags::ZERO | StatusFlags::CARRY);
+
+    process_instruction(&mut cpu, &[0x6A]);
+    assert_eq!(cpu.registers.a, 0x80);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
 }
 
 #[test]
@@ -875,11 +1672,28 @@ fn process_rti_implied() {
     process_instruction(&mut cpu, &[0x00]);
     cpu.registers.p = StatusFlags::empty();
 
+    // the break bits BRK pushed are not restored
     process_instruction(&mut cpu, &[0x40]);
-    assert_eq!(cpu.registers.p, flags);
+    assert_eq!(cpu.registers.p, flags | StatusFlags::BREAK_LEFT);
     assert_eq!(cpu.registers.pc, pc_old);
 }
 
+#[test]
+fn process_rti_rts_cycles() {
+    let mut cpu = cpu(bus());
+    cpu.stack_push_u16(ADDRESS_PRG);
+    cpu.stack_push(StatusFlags::ZERO.bits());
+
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 6);
+
+    cpu.stack_push_u16(ADDRESS_PRG + 2);
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0x60]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 6);
+}
+
 #[test]
 fn process_rts_implied() {
     let mut cpu = cpu(bus());
@@ -893,7 +1707,59 @@ fn process_rts_implied() {
     process_instruction(&mut cpu, &[0xEA]);
 
     process_instruction(&mut cpu, &[0x60]);
-    assert_eq!(cpu.registers.pc, pc_old + 2);
+    assert_eq!(cpu.registers.pc, pc_old + 3);
+}
+
+#[test]
+fn jsr_rts_round_trip() {
+    // JSR $8010, LDX #$01, JAM; at $8010: LDA #$42, RTS
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0x20, 0x10, 0x80, 0xA2, 0x01, 0x02]).unwrap();
+    bus.write_n(ADDRESS_PRG + 0x10, &[0xA9, 0x42, 0x60]).unwrap();
+    let mut cpu = cpu(bus);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.pc, 0x8010);
+    assert_eq!(cpu.registers.s, 0xFD);
+    assert_eq!(cpu.bus.read_u16(0x01FE).unwrap(), 0x8002);
+    assert_eq!(cpu.cycle_count(), 6);
+
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(cpu.registers.s, 0xFF);
+    assert_eq!(cpu.cycle_count(), 14);
+
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!((cpu.registers.a, cpu.registers.x), (0x42, 0x01));
+}
+
+#[test]
+fn call() {
+    // at $8010: LDA #$42, RTS; at $8020: JAM; at $8030: INX, JMP $8030
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG + 0x10, &[0xA9, 0x42, 0x60]).unwrap();
+    bus.write_n(ADDRESS_PRG + 0x30, &[0xE8, 0x4C, 0x30, 0x80]).unwrap();
+    bus.write(ADDRESS_PRG + 0x20, 0x02);
+    let mut cpu = cpu(bus);
+
+    cpu.call(0x8010, 10).unwrap();
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(cpu.registers.pc, 0x0000);
+    assert_eq!(cpu.registers.s, 0xFF);
+    assert_eq!(cpu.cycle_count(), 8);
+
+    cpu.registers.pc = ADDRESS_PRG;
+    assert_eq!(
+        cpu.call(0x8030, 10).unwrap_err().to_string(),
+        "subroutine at `$8030` did not return within `10` instructions",
+    );
+    assert_eq!(cpu.registers.x, 5);
+    assert_eq!((cpu.registers.s, cpu.registers.pc), (0xFF, ADDRESS_PRG));
+
+    assert!(cpu.call(0x8020, 10).is_err());
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!((cpu.registers.s, cpu.registers.pc), (0xFF, ADDRESS_PRG));
 }
 
 #[test]
@@ -1073,3 +1939,1466 @@ fn process_tya_implied() {
     assert_eq!(cpu.registers.a, 0x80);
     assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
 }
+
+#[test]
+fn transfer_flags() {
+    // LDA #$00, TAX, LDA #$80, TAY, LDX #$00, TXS, LDX #$80, LDA #$00, TXS
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x00, 0xAA, 0xA9, 0x80, 0xA8, 0xA2, 0x00, 0x9A, 0xA2, 0x80, 0xA9, 0x00, 0x9A]).unwrap();
+    let mut cpu = cpu(bus);
+
+    let step = |cpu: &mut Cpu, count: u64| {
+        assert_eq!(cpu.run_for(count).unwrap(), count);
+        cpu.registers.p
+    };
+
+    assert_eq!(step(&mut cpu, 2), StatusFlags::ZERO);
+    assert_eq!(step(&mut cpu, 2), StatusFlags::NEGATIVE);
+    assert_eq!(step(&mut cpu, 2), StatusFlags::ZERO);
+    assert_eq!(cpu.registers.s, 0x00);
+
+    // TXS is the only transfer that leaves N and Z alone, so a negative X does not show up in P
+    assert_eq!(step(&mut cpu, 3), StatusFlags::ZERO);
+    assert_eq!(cpu.registers.s, 0x80);
+}
+
+#[test]
+fn operand_out_of_range() {
+    let cpu = cpu(bus());
+    assert_eq!(operand(&[0x34, 0x12]).as_slice(), &[0x34, 0x12]);
+    assert_eq!(operand(&[0x34, 0x12]).word().unwrap(), 0x1234);
+    assert!(OperandBytes::new(&[0x00, 0x00, 0x00]).is_err());
+
+    let error = cpu.determine_input(InstructionMode::Absolute, &operand(&[0x34])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::OperandOutOfRange { index: 1, len: 1 }));
+
+    let error = cpu.determine_input(InstructionMode::Immediate, &operand(&[])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::OperandOutOfRange { index: 0, len: 0 }));
+}
+
+#[test]
+fn execute_instruction_error_annotated() {
+    let mut cpu = cpu(bus());
+    let instruction = Instruction::new(InstructionOperation::Sta, InstructionMode::Implied, 2).with_opcode(0x85);
+
+    cpu.registers.pc = ADDRESS_PRG + 1;
+    let error = cpu.execute(instruction, operand(&[])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::ExecutionFailed {
+        pc: ADDRESS_PRG,
+        opcode: 0x85,
+        mode: InstructionMode::Implied,
+        operand: vec![],
+    }));
+    assert_eq!(error.root_cause().to_string(), "input is not a location");
+
+    cpu.registers.pc = 0x8005;
+    let instruction = Instruction::new(InstructionOperation::Asl, InstructionMode::Immediate, 2).with_opcode(0x0A);
+    let error = cpu.execute(instruction, operand(&[INPUT_BYTE])).unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "opcode `$0A` (Immediate) at `$8003` with operand [4F] failed: operation `Asl` does not support input mode `Immediate`",
+    );
+    assert_eq!(error.root_cause().downcast_ref::<CpuError>(), Some(&CpuError::InvalidInputMode {
+        operation: InstructionOperation::Asl,
+        mode: InstructionMode::Immediate,
+    }));
+}
+
+#[test]
+fn call_instruction_invalid_input_mode() {
+    let mut cpu = cpu(bus());
+
+    let instruction = Instruction::new(InstructionOperation::Sta, InstructionMode::Immediate, 2);
+    let error = cpu.call_instruction(instruction, operand(&[INPUT_BYTE])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::InvalidInputMode {
+        operation: InstructionOperation::Sta,
+        mode: InstructionMode::Immediate,
+    }));
+
+    let instruction = Instruction::new(InstructionOperation::Asl, InstructionMode::Immediate, 2);
+    let error = cpu.call_instruction(instruction, operand(&[INPUT_BYTE])).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::InvalidInputMode {
+        operation: InstructionOperation::Asl,
+        mode: InstructionMode::Immediate,
+    }));
+}
+
+#[test]
+fn status_flags_decompose() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0x7F);
+    cpu.registers.p.insert(StatusFlags::DECIMAL);
+    process_instruction(&mut cpu, &[0x69, 0x01]);
+
+    let flags = cpu.registers.p.decompose();
+    assert!(flags.negative);
+    assert!(flags.overflow);
+    assert!(flags.decimal);
+    assert!(!flags.interrupt);
+    assert!(!flags.zero);
+    assert!(!flags.carry);
+    assert_eq!(StatusFlags::default().decompose(), FlagStates { interrupt: true, ..FlagStates::default() });
+}
+
+#[test]
+fn decimal_mode_variants() {
+    let adc = |variant: CpuVariant| {
+        let mut cpu = cpu(bus());
+        cpu.options_mut().set_variant(variant);
+        cpu.registers.a = 0x99;
+        cpu.registers.p = StatusFlags::DECIMAL;
+        let cycles = cpu.execute_opcode(0x69, &[0x01]).unwrap().cycles();
+        (cpu.registers.a, cpu.registers.p - StatusFlags::DECIMAL, cycles)
+    };
+
+    assert_eq!(adc(CpuVariant::Ricoh2A03), (0x9A, StatusFlags::NEGATIVE, 2));
+    assert_eq!(adc(CpuVariant::Mos6502), (0x00, StatusFlags::NEGATIVE | StatusFlags::CARRY, 2));
+    assert_eq!(adc(CpuVariant::Cmos65C02), (0x00, StatusFlags::ZERO | StatusFlags::CARRY, 3));
+
+    let mut cpu = cpu(bus());
+    cpu.options_mut().set_variant(CpuVariant::Cmos65C02);
+    cpu.registers.p = StatusFlags::DECIMAL | StatusFlags::CARRY;
+    assert_eq!(cpu.execute_opcode(0xE9, &[0x01]).unwrap().cycles(), 3);
+    assert_eq!(cpu.registers.a, 0x99);
+    assert!(cpu.registers.p.contains(StatusFlags::NEGATIVE));
+}
+
+#[test]
+fn execute_opcode_adc_immediate() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0x10);
+    let pc_old = cpu.registers.pc;
+
+    let result = cpu.execute_opcode(0x69, &[0x20]).unwrap();
+    assert_eq!(result.instruction().operation(), InstructionOperation::Adc);
+    assert_eq!(result.cycles(), 2);
+    assert_eq!(cpu.registers.a, 0x30);
+    assert_eq!(cpu.registers.pc, pc_old + 2);
+    assert_eq!(cpu.bus.read(pc_old), 0x00);
+
+    let error = cpu.execute_opcode(0x69, &[]).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::InvalidOperandLength {
+        expected: 1,
+        received: 0,
+    }));
+}
+
+#[test]
+fn write_trace_reused_buffer() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x8D, 0x00, 0x02, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+
+    let mut trace = String::new();
+    for _ in 0..3 {
+        cpu.write_trace(&mut trace).unwrap();
+        trace.push('\n');
+
+        cpu.run_instruction().unwrap().unwrap();
+    }
+
+    assert_eq!(trace, concat!(
+        "8000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:00 SP:FF CYC:0\n",
+        "8002  8D 00 02  STA $0200 = 00                  A:42 X:00 Y:00 P:00 SP:FF CYC:2\n",
+        "8005  EA        NOP                             A:42 X:00 Y:00 P:00 SP:FF CYC:6\n",
+    ));
+    assert_eq!(cpu.trace_line(), "8006  00        BRK                             A:42 X:00 Y:00 P:00 SP:FF CYC:8");
+}
+
+#[test]
+fn attach_trace_writer() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x8D, 0x00, 0x02, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+
+    let buffer = Rc::new(RefCell::new(vec![]));
+    cpu.attach_trace_writer(Box::new(SharedBuffer(buffer.clone())));
+    cpu.run_for(3).unwrap();
+    // buffered until detached
+    assert!(buffer.borrow().is_empty());
+
+    cpu.detach_trace_writer().unwrap();
+    cpu.run_for(1).unwrap();
+    assert_eq!(String::from_utf8(buffer.borrow().clone()).unwrap(), concat!(
+        "8000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:00 SP:FF CYC:0\n",
+        "8002  8D 00 02  STA $0200 = 00                  A:42 X:00 Y:00 P:00 SP:FF CYC:2\n",
+        "8005  EA        NOP                             A:42 X:00 Y:00 P:00 SP:FF CYC:6\n",
+    ));
+}
+
+#[test]
+fn attach_trace_writer_side_effect_free() {
+    // LDA $4016 twice with A held and B released, then the same with a trace attached
+    let run = |traced: bool| {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, &[0xAD, 0x16, 0x40, 0xAD, 0x16, 0x40]).unwrap();
+        bus.controller_mut().press(JoypadButton::A);
+        bus.write(0x4016, 0x01);
+        bus.write(0x4016, 0x00);
+        let mut cpu = cpu(bus);
+        if traced {
+            side_effect_free(&mut cpu, |cpu| cpu.trace_line());
+            cpu.attach_trace_writer(Box::new(std::io::sink()));
+        }
+
+        let mut loaded = vec![];
+        for _ in 0..2 {
+            cpu.run_instruction().unwrap().unwrap();
+            loaded.push(cpu.registers.a);
+        }
+        loaded
+    };
+
+    assert_eq!(run(false), vec![0x01, 0x00]);
+    assert_eq!(run(true), run(false));
+}
+
+#[test]
+fn run_for_closure_memory() {
+    let memory = ClosureMemory::new(|_| 0xEA, |_, _| {});
+    let mut cpu = Cpu::new(Bus::with_memory(Box::new(memory))).unwrap();
+    assert_eq!(cpu.registers.pc, 0xEAEA);
+
+    assert_eq!(cpu.run_for(1000).unwrap(), 1000);
+    assert_eq!(cpu.registers.pc, 0xEAEA + 1000);
+    assert_eq!(cpu.clock.cycles(), 2000);
+}
+
+#[test]
+fn cycles_page_cross() {
+    let mut cpu = cpu(bus());
+    cpu.registers.x = 0x01;
+
+    // LDA $40FE,X
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0xBD, 0xFE, 0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 4);
+
+    // LDA $40FF,X
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0xBD, 0xFF, 0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 5);
+
+    // ASL $40FE,X
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0x1E, 0xFE, 0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 7);
+
+    // ASL $40FF,X
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0x1E, 0xFF, 0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 7);
+
+    // STA $40FF,X
+    let cycles_old = cpu.cycle_count();
+    process_instruction(&mut cpu, &[0x9D, 0xFF, 0x40]);
+    assert_eq!(cpu.cycle_count() - cycles_old, 5);
+}
+
+#[test]
+fn reset_cycles() {
+    let mut cpu = run_program(&[0xEA, 0xEA, 0xA9, 0x10, 0xE8], 2);
+    assert_eq!(cpu.cycle_count(), 4);
+
+    cpu.reset_cycles();
+    assert_eq!(cpu.cycle_count(), 0);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.cycle_count(), 4);
+    assert_eq!(cpu.registers.a, 0x10);
+    assert_eq!(cpu.registers.x, 0x01);
+}
+
+#[test]
+fn load_state_scheduled_interrupt() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xEA; 8]).unwrap();
+    let mut cpu = cpu(bus);
+    let state = cpu.save_state();
+    cpu.run_for(3).unwrap();
+
+    cpu.schedule_nmi(5);
+    cpu.trigger_irq();
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.cycle_count(), 0);
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(5));
+
+    // the IRQ raised before the load is gone, the NMI is due after the third NOP
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(1));
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+}
+
+#[test]
+fn reset_cycles_scheduled_interrupt() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xEA; 8]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.run_for(3).unwrap();
+
+    cpu.schedule_nmi(5);
+    cpu.reset_cycles();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(5));
+
+    // due after the third NOP from here, not three NOPs later
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(1));
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), None);
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+}
+
+#[test]
+fn resolve_operand_indexed() {
+    let mut bus = bus();
+    bus.write(0x0205, 0x42);
+    bus.write_n(ADDRESS_PRG, &[0xBD, 0x00, 0x02]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.x = 0x05;
+
+    let instruction = Instruction::decode(0xBD);
+    let operand = cpu.resolve_operand(instruction, &[0xBD, 0x00, 0x02]).unwrap();
+    assert_eq!(operand, ResolvedOperand { pointer: None, address: Some(0x0205), value: Some(0x42) });
+
+    assert_eq!(cpu.trace_line(), "8000  BD 00 02  LDA $0200,X @ 0205 = 42         A:00 X:05 Y:00 P:00 SP:FF CYC:0");
+}
+
+#[test]
+fn rewind() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xE8; 0x20]).unwrap();
+    let mut cpu = cpu(bus);
+    assert!(!cpu.rewind().unwrap());
+
+    cpu.set_rewind(3, 2);
+    cpu.run_for(4).unwrap();
+    let state = cpu.save_state();
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.x, 0x05);
+
+    assert!(cpu.rewind().unwrap());
+    assert_eq!(cpu.save_state(), state);
+    assert!(cpu.rewind().unwrap());
+    assert_eq!(cpu.registers.x, 0x02);
+    assert_eq!(cpu.cycle_count(), 4);
+    assert!(!cpu.rewind().unwrap());
+
+    // only the last three are kept
+    cpu.run_for(12).unwrap();
+    assert_eq!(cpu.registers.x, 0x0E);
+    for x in &[0x0E, 0x0C, 0x0A] {
+        assert!(cpu.rewind().unwrap());
+        assert_eq!(cpu.registers.x, *x);
+    }
+    assert!(!cpu.rewind().unwrap());
+}
+
+#[test]
+fn rewind_side_effect_free() {
+    let mut bytes = image(1, 0, 0b0000_0010, 0);
+    // INC $6000 four times
+    for i in 0..4 {
+        bytes[16 + i * 3..16 + i * 3 + 3].copy_from_slice(&[0xEE, 0x00, 0x60]);
+    }
+    bytes[16 + 0x3FFD] = 0x80;
+    let mut bus = Bus::new();
+    bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+    bus.write(0x07FF, 0x5A);
+    let mut cpu = cpu(bus);
+    cpu.set_rewind(4, 1);
+    cpu.bus.set_access_log_capacity(64);
+
+    // 3 fetches, a read and a write per INC, and nothing from the snapshot after each
+    cpu.run_for(4).unwrap();
+    assert_eq!(cpu.bus.access_log().len(), 4 * 5);
+    assert_eq!(cpu.bus.open_bus(), 0x04);
+    assert_eq!(cpu.save_ram()[0], 0x04);
+
+    side_effect_free(&mut cpu, |cpu| cpu.save_state());
+
+    assert!(cpu.rewind().unwrap());
+    assert!(cpu.rewind().unwrap());
+    assert_eq!(cpu.save_ram()[0], 0x03);
+}
+
+#[test]
+fn set_registers() {
+    let mut bus = bus();
+    bus.write(0xC000, 0xEA);
+    let mut cpu = cpu(bus);
+    cpu.set_registers(0x01, 0x02, 0x03, 0xFD, 0x24, 0xC000);
+
+    assert_eq!(cpu.trace_line(), "C000  EA        NOP                             A:01 X:02 Y:03 P:24 SP:FD CYC:0");
+}
+
+#[test]
+fn set_sp() {
+    let mut cpu = cpu(bus());
+    cpu.set_sp(0x80);
+    assert_eq!(cpu.sp(), 0x80);
+
+    cpu.registers.a = INPUT_BYTE;
+    process_instruction(&mut cpu, &[0x48]);
+    assert_eq!(cpu.bus.read(0x0180), INPUT_BYTE);
+    assert_eq!(cpu.sp(), 0x7F);
+}
+
+#[test]
+fn resolve_operand_indirect_y() {
+    let mut bus = bus();
+    bus.write_u16(INPUT_ADDRESS_ZP, INPUT_ADDRESS).unwrap();
+    bus.write(INPUT_ADDRESS + OFFSET_REGISTER_Y as u16, 0x89);
+    bus.write_n(ADDRESS_PRG, &[0xB1, INPUT_ADDRESS_ZP as u8]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.y = OFFSET_REGISTER_Y;
+
+    assert!(cpu.trace_line().starts_with("8000  B1 40     LDA ($40),Y = 4020 @ 4044 = 89  A:00"));
+}
+
+#[test]
+fn events_capacity() {
+    let mut bus = bus();
+    // LDA #$8D (STA); STA $8005; STA $8008; STA $800B; NOP
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x8D, 0x8D, 0x05, 0x80, 0x8D, 0x08, 0x80, 0x8D, 0x0B, 0x80, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_detect_self_modifying_code(true);
+    cpu.set_events_capacity(2);
+
+    cpu.run_for(4).unwrap();
+    assert_eq!(cpu.events(), &[
+        CpuEvent::SelfModifyingWrite { pc: 0x8005, address: 0x8008, value: 0x8D },
+        CpuEvent::SelfModifyingWrite { pc: 0x8008, address: 0x800B, value: 0x8D },
+    ]);
+
+    cpu.set_events_capacity(1);
+    assert_eq!(cpu.events(), &[CpuEvent::SelfModifyingWrite { pc: 0x8008, address: 0x800B, value: 0x8D }]);
+
+    cpu.set_events_capacity(0);
+    assert!(cpu.events().is_empty());
+}
+
+#[test]
+fn detect_self_modifying_code() {
+    let mut bus = bus();
+    // LDA #$E8 (INX); STA $8005; NOP
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0xE8, 0x8D, 0x05, 0x80, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_detect_self_modifying_code(true);
+
+    cpu.run_for(3).unwrap();
+    assert_eq!(cpu.registers.x, 0x01);
+    assert_eq!(cpu.take_events(), vec![
+        CpuEvent::SelfModifyingWrite { pc: 0x8002, address: 0x8005, value: 0xE8 },
+    ]);
+    assert!(cpu.events().is_empty());
+}
+
+#[test]
+fn strict_rom() {
+    let mut bytes = image(1, 0, 0, 0);
+    // LDA #$42; STA $8100; NOP
+    bytes[16..22].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x81, 0xEA]);
+    bytes[16 + 0x3FFD] = 0x80;
+
+    for strict in [false, true] {
+        let mut bus = Bus::new();
+        bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_strict_rom(strict);
+
+        cpu.run_for(3).unwrap();
+        assert_eq!(cpu.registers.pc, 0x8006);
+        assert_eq!(cpu.bus.read(0x8100), 0x00);
+
+        let events = cpu.take_events();
+        if strict {
+            assert_eq!(events, vec![CpuEvent::RomWrite { pc: 0x8002, address: 0x8100, value: 0x42 }]);
+        } else {
+            assert!(events.is_empty());
+        }
+    }
+}
+
+#[test]
+fn reset_vector_unmapped() {
+    let bytes = image(1, 0, 0, 0);
+    let bus = || {
+        let mut bus = Bus::new();
+        bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+        bus
+    };
+
+    let cpu = Cpu::new(bus()).unwrap();
+    assert_eq!(cpu.registers.pc, 0x0000);
+
+    let mut options = CpuOptions::default();
+    options.set_strict_rom(true);
+    let error = Cpu::with_options(bus(), options).err().unwrap();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::UnmappedResetVector { address: 0x0000 }));
+}
+
+#[test]
+fn save_ram_round_trip() {
+    let mut bytes = image(1, 0, 0b0000_0010, 0);
+    // LDA #$42; STA $6000; LDA #$00; LDA $6000
+    bytes[16..26].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x60, 0xA9, 0x00, 0xAD, 0x00, 0x60]);
+    bytes[16 + 0x3FFD] = 0x80;
+    let machine = || {
+        let mut bus = Bus::new();
+        bus.insert_cartridge(Cartridge::from_bytes(&bytes).unwrap());
+        cpu(bus)
+    };
+
+    let mut cpu = machine();
+    cpu.run_for(4).unwrap();
+    assert_eq!(cpu.registers.a, 0x42);
+    let save_ram = cpu.save_ram().to_vec();
+    assert_eq!(save_ram.len(), 0x2000);
+    assert_eq!(save_ram[0], 0x42);
+
+    let mut cpu = machine();
+    assert_eq!(cpu.bus.read(0x6000), 0x00);
+    assert!(cpu.load_save_ram(&[0x42]).is_err());
+    cpu.load_save_ram(&save_ram).unwrap();
+    assert_eq!(cpu.bus.read(0x6000), 0x42);
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x85, 0x10, 0xA2, 0x07, 0x48]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.run_for(4).unwrap();
+
+    let state = cpu.save_state();
+    let mut buffer = vec![];
+    state.save_to_writer(&mut buffer).unwrap();
+    let state_loaded = SaveState::load_from_reader(&mut buffer.as_slice()).unwrap();
+    assert_eq!(state_loaded, state);
+
+    let mut cpu_loaded = Cpu::new(Bus::new()).unwrap();
+    cpu_loaded.load_state(&state_loaded).unwrap();
+    assert_eq!(cpu_loaded.registers, cpu.registers);
+    assert_eq!(cpu_loaded.cycle_count(), cpu.cycle_count());
+    assert_eq!(cpu_loaded.bus.read(0x0010), 0x42);
+    assert_eq!(cpu_loaded.bus.read(0x01FF), 0x42);
+
+    // battery-backed PRG-RAM travels with the state, and only fits a machine with the same amount
+    let machine = || {
+        let mut bus = Bus::new();
+        bus.insert_cartridge(Cartridge::from_bytes(&image(1, 0, 0b0000_0010, 0)).unwrap());
+        Cpu::new(bus).unwrap()
+    };
+    let mut cpu = machine();
+    cpu.bus.write(0x6000, 0x24);
+    cpu.bus.write(0x7FFF, 0x42);
+
+    let mut buffer = vec![];
+    cpu.save_state().save_to_writer(&mut buffer).unwrap();
+    let state = SaveState::load_from_reader(&mut buffer.as_slice()).unwrap();
+    cpu.bus.write(0x6000, 0x00);
+    cpu.load_state(&state).unwrap();
+    assert_eq!((cpu.bus.read(0x6000), cpu.bus.read(0x7FFF)), (0x24, 0x42));
+
+    let mut cpu_loaded = machine();
+    cpu_loaded.load_state(&state).unwrap();
+    assert_eq!(cpu_loaded.save_ram(), cpu.save_ram());
+    assert!(Cpu::new(Bus::new()).unwrap().load_state(&state).is_err());
+}
+
+#[test]
+fn save_state_invalid() {
+    let mut buffer = vec![];
+    cpu(bus()).save_state().save_to_writer(&mut buffer).unwrap();
+
+    let mut buffer_magic = buffer.clone();
+    buffer_magic[0] = b'X';
+    let error = SaveState::load_from_reader(&mut buffer_magic.as_slice()).unwrap_err();
+    assert!(error.to_string().contains("magic"));
+
+    let mut buffer_version = buffer.clone();
+    buffer_version[4] = 0xFF;
+    let error = SaveState::load_from_reader(&mut buffer_version.as_slice()).unwrap_err();
+    assert!(error.to_string().contains("version"));
+
+    // rejected before allocating, rather than reserving 4 GiB
+    let mut buffer_len = buffer;
+    buffer_len[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = SaveState::load_from_reader(&mut buffer_len.as_slice()).unwrap_err();
+    assert_eq!(error.to_string(), "expected registers to have length at most `15`, received `4294967295`");
+
+    // the cartridge RAM section is last, with nothing but its length left in a truncated file
+    let mut buffer_cartridge = vec![];
+    cpu(bus()).save_state().save_to_writer(&mut buffer_cartridge).unwrap();
+    let len_at = buffer_cartridge.len() - 4;
+    buffer_cartridge[len_at..].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = SaveState::load_from_reader(&mut buffer_cartridge.as_slice()).unwrap_err();
+    assert!(error.to_string().starts_with("expected cartridge RAM to have length at most"));
+}
+
+#[test]
+fn compare_registers() {
+    let cases = [
+        (0x20, 0x10, StatusFlags::CARRY),
+        (0xAA, 0xAA, StatusFlags::ZERO | StatusFlags::CARRY),
+        (0x80, 0xFF, StatusFlags::NEGATIVE),
+        (0x00, 0x01, StatusFlags::NEGATIVE),
+        (0xFF, 0x00, StatusFlags::NEGATIVE | StatusFlags::CARRY),
+    ];
+
+    for (register, input, flags) in cases.iter().copied() {
+        let mut cpu = cpu(bus());
+        cpu.registers.a = register;
+        cpu.registers.x = register;
+        cpu.registers.y = register;
+
+        cpu.registers.p = StatusFlags::empty();
+        cpu.run_cmp(input);
+        assert_eq!(cpu.registers.p, flags);
+
+        cpu.registers.p = StatusFlags::empty();
+        cpu.run_cpx(input);
+        assert_eq!(cpu.registers.p, flags);
+
+        cpu.registers.p = StatusFlags::empty();
+        cpu.run_cpy(input);
+        assert_eq!(cpu.registers.p, flags);
+    }
+}
+
+#[test]
+fn restrict_to() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x10, 0x69, 0x01]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.restrict_to([InstructionOperation::Lda, InstructionOperation::Jmp].iter().copied().collect());
+
+    assert_eq!(cpu.run_for(1).unwrap(), 1);
+    assert_eq!(cpu.registers.a, 0x10);
+
+    let error = cpu.run_for(1).unwrap_err();
+    assert_eq!(error.downcast_ref::<CpuError>(), Some(&CpuError::RestrictedOperation {
+        operation: InstructionOperation::Adc,
+    }));
+    assert_eq!(cpu.registers.a, 0x10);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 2);
+}
+
+#[test]
+fn status_flags_display_string() {
+    assert_eq!(StatusFlags::from_bits_truncate(0x24).to_display_string(), "nv-bdIzc");
+    assert_eq!(StatusFlags::all().to_display_string(), "NV-BDIZC");
+    assert_eq!(StatusFlags::empty().to_display_string(), "nv-bdizc");
+    assert_eq!((StatusFlags::NEGATIVE | StatusFlags::CARRY).to_display_string(), "Nv-bdizC");
+}
+
+#[test]
+fn process_implied_skips_operand_fetch() {
+    let reads = Rc::new(Cell::new(0));
+    let reads_memory = reads.clone();
+    let memory = ClosureMemory::new(move |_| {
+        reads_memory.set(reads_memory.get() + 1);
+        0xEA
+    }, |_, _| {});
+    let mut cpu = Cpu::new(Bus::with_memory(Box::new(memory))).unwrap();
+
+    reads.set(0);
+    cpu.run_for(1).unwrap();
+    assert_eq!(reads.get(), 1);
+    assert_eq!(cpu.registers.pc, 0xEAEB);
+}
+
+#[test]
+fn process_absolute_fetches_once() {
+    let reads = Rc::new(Cell::new(0));
+    let reads_memory = reads.clone();
+    // LDA $ADAD everywhere
+    let memory = ClosureMemory::new(move |_| {
+        reads_memory.set(reads_memory.get() + 1);
+        0xAD
+    }, |_, _| {});
+    let mut cpu = Cpu::new(Bus::with_memory(Box::new(memory))).unwrap();
+
+    reads.set(0);
+    cpu.run_for(1).unwrap();
+    assert_eq!(reads.get(), 4);
+    assert_eq!(cpu.registers.a, 0xAD);
+}
+
+#[test]
+fn tick_completes_on_last_cycle() {
+    let mut bus = bus();
+    bus.write(INPUT_ADDRESS, 0x42);
+    bus.write_n(ADDRESS_PRG, &[0xAD, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+
+    for tick in 1..4 {
+        assert!(!cpu.tick().unwrap());
+        assert_eq!(cpu.ticks_this_instruction(), tick);
+    }
+    assert!(cpu.tick().unwrap());
+    assert_eq!(cpu.ticks_this_instruction(), 4);
+    assert_eq!(cpu.registers.a, 0x42);
+
+    assert!(!cpu.tick().unwrap());
+    assert_eq!(cpu.ticks_this_instruction(), 1);
+    assert!(cpu.tick().unwrap());
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 4);
+}
+
+#[test]
+fn step_jam_halts() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xEA, 0x02, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+
+    assert_eq!(cpu.step().unwrap(), CpuStatus::Running);
+    assert_eq!(cpu.step().unwrap(), CpuStatus::Halted);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+
+    let cycles = cpu.cycle_count();
+    assert_eq!(cpu.step().unwrap(), CpuStatus::Halted);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+    assert_eq!(cpu.cycle_count(), cycles);
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+}
+
+#[test]
+fn step_wait_for_interrupt() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xE8]).unwrap();
+    let mut cpu = cpu(bus);
+
+    // idles a cycle per step, fetching nothing, until the NMI comes due
+    cpu.wait_for_interrupt();
+    cpu.schedule_nmi(2);
+    assert_eq!(cpu.step().unwrap(), CpuStatus::WaitingForInterrupt);
+    assert_eq!(cpu.step().unwrap(), CpuStatus::WaitingForInterrupt);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG);
+    assert_eq!(cpu.registers.x, 0x00);
+    assert_eq!(cpu.cycle_count(), 2);
+
+    assert_eq!(cpu.step().unwrap(), CpuStatus::Running);
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+    assert_eq!(cpu.cycle_count(), 2 + 7);
+    cpu.stack_pull();
+    assert_eq!(cpu.stack_pull_u16(), ADDRESS_PRG);
+
+    // a triggered NMI resumes it just the same
+    cpu.registers.pc = ADDRESS_PRG;
+    cpu.wait_for_interrupt();
+    cpu.trigger_nmi();
+    assert_eq!(cpu.step().unwrap(), CpuStatus::Running);
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+}
+
+#[test]
+fn execution_hash_deterministic() {
+    let run = |program: &[u8]| {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, program).unwrap();
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_hash_execution(true);
+        cpu.run_for(6).unwrap();
+        cpu.execution_hash()
+    };
+
+    // LDX #$02; DEX; BNE -3; NOP
+    let program = [0xA2, 0x02, 0xCA, 0xD0, 0xFD, 0xEA];
+    let hash = run(&program);
+    assert_eq!(run(&program), hash);
+    assert_ne!(hash, FNV_OFFSET_BASIS);
+
+    // LDX #$03; DEX; BNE -3; NOP
+    assert_ne!(run(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xEA]), hash);
+
+    let mut cpu = cpu(bus());
+    cpu.run_for(4).unwrap();
+    assert_eq!(cpu.execution_hash(), FNV_OFFSET_BASIS);
+}
+
+#[test]
+fn disassemble_ahead() {
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x8D, 0x00, 0x02, 0xE8]).unwrap();
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.disassemble_ahead(3), vec![
+        (0x8000, String::from("LDA #$42")),
+        (0x8002, String::from("STA $0200")),
+        (0x8005, String::from("INX")),
+    ]);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG);
+}
+
+#[test]
+fn disassemble_region() {
+    let mut bus = bus();
+    // LDA #$42; JAM; STA $0200; SLO $10, whose operand decodes as BPL once SLO is skipped; BRK
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x02, 0x8D, 0x00, 0x02, 0x07, 0x10, 0x4C, 0x00]).unwrap();
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.disassemble_region(ADDRESS_PRG, ADDRESS_PRG + 9), vec![
+        (0x8000, vec![0xA9, 0x42], String::from("LDA #$42")),
+        (0x8002, vec![0x02], String::from(".byte $02")),
+        (0x8003, vec![0x8D, 0x00, 0x02], String::from("STA $0200")),
+        (0x8006, vec![0x07], String::from(".byte $07")),
+        (0x8007, vec![0x10, 0x4C], String::from("BPL $8055")),
+        (0x8009, vec![0x00], String::from("BRK")),
+    ]);
+    assert_eq!(cpu.disassemble_region(ADDRESS_PRG + 8, ADDRESS_PRG + 9), vec![
+        (0x8008, vec![0x4C], String::from(".byte $4C")),
+        (0x8009, vec![0x00], String::from("BRK")),
+    ]);
+    assert_eq!(cpu.disassemble_region(0xFFFF, 0xFFFF).len(), 1);
+}
+
+#[test]
+fn disassemble_with_labels() {
+    let mut bus = bus();
+    // LDX #$03; DEX; BNE -3; JSR $800B; JMP $C000; RTS
+    bus.write_n(ADDRESS_PRG, &[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x20, 0x0B, 0x80, 0x4C, 0x00, 0xC0, 0x60]).unwrap();
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.disassemble_with_labels(ADDRESS_PRG, ADDRESS_PRG + 11), concat!(
+        "    LDX #$03\n",
+        "L_8002:\n",
+        "    DEX\n",
+        "    BNE L_8002\n",
+        "    JSR L_800B\n",
+        "    JMP $C000\n",
+        "L_800B:\n",
+        "    RTS\n",
+    ));
+}
+
+#[test]
+fn disassemble_with_labels_unaligned() {
+    let mut bus = bus();
+    // LDA #$EA; JMP $8001; BNE +0; .byte $02
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0xEA, 0x4C, 0x01, 0x80, 0xD0, 0x00, 0x02]).unwrap();
+    let cpu = cpu(bus);
+
+    // the JMP lands on the operand of LDA and the BNE on a data byte, neither starts a line
+    assert_eq!(cpu.disassemble_with_labels(ADDRESS_PRG, ADDRESS_PRG + 7), concat!(
+        "    LDA #$EA\n",
+        "    JMP $8001\n",
+        "    BNE $8007\n",
+        "    .byte $02\n",
+    ));
+}
+
+#[test]
+fn vectors_little_endian() {
+    let mut bus = Bus::new();
+    for (i, byte) in [0x34, 0x12, 0x00, 0x80, 0xBC, 0x9A].iter().enumerate() {
+        bus.write(ADDRESS_VECTOR_NMI + i as u16, *byte);
+    }
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.vectors.nmi, 0x1234);
+    assert_eq!(cpu.vectors.reset, 0x8000);
+    assert_eq!(cpu.vectors.irq, 0x9ABC);
+    assert_eq!(cpu.registers.pc, 0x8000);
+}
+
+#[test]
+fn operands_little_endian() {
+    let mut bus = bus();
+    bus.write(0x1234, 0x42);
+    bus.write_n(0x0300, &[0x78, 0x56]).unwrap();
+    // LDA $1234; JMP ($0300)
+    bus.write_n(ADDRESS_PRG, &[0xAD, 0x34, 0x12, 0x6C, 0x00, 0x03]).unwrap();
+    let mut cpu = cpu(bus);
+
+    assert_eq!(cpu.decode(ADDRESS_PRG).1, Operand::Absolute(0x1234));
+    assert_eq!(cpu.decode(ADDRESS_PRG + 3).1, Operand::Indirect(0x0300));
+
+    cpu.run_for(2).unwrap();
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(cpu.registers.pc, 0x5678);
+}
+
+#[test]
+fn reload_vectors() {
+    let mut cpu = cpu(bus());
+    cpu.bus.write_u16(ADDRESS_VECTOR_NMI, 0x1234).unwrap();
+    cpu.bus.write_u16(ADDRESS_VECTOR_RESET, 0x5678).unwrap();
+    cpu.bus.write_u16(ADDRESS_VECTOR_IRQ, 0x9ABC).unwrap();
+    assert_eq!(cpu.vectors.reset, ADDRESS_PRG);
+
+    cpu.reload_vectors().unwrap();
+    assert_eq!(cpu.vectors.nmi, 0x1234);
+    assert_eq!(cpu.vectors.reset, 0x5678);
+    assert_eq!(cpu.vectors.irq, 0x9ABC);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG);
+}
+
+#[test]
+fn decode() {
+    let mut bus = bus();
+    // LDA #$42; STA $0200; BNE -4; LDA ($40),Y; ASL A; NOP
+    bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0x8D, 0x00, 0x02, 0xD0, 0xFC, 0xB1, 0x40, 0x0A, 0xEA, 0xFF]).unwrap();
+    let cpu = cpu(bus);
+
+    assert_eq!(cpu.decode(0x8000), (InstructionOperation::Lda, Operand::Immediate(0x42)));
+    assert_eq!(cpu.decode(0x8002), (InstructionOperation::Sta, Operand::Absolute(0x0200)));
+    assert_eq!(cpu.decode(0x8005), (InstructionOperation::Bne, Operand::Relative(-4)));
+    assert_eq!(cpu.decode(0x8007), (InstructionOperation::Lda, Operand::IndirectY(0x40)));
+    assert_eq!(cpu.decode(0x8009), (InstructionOperation::Asl, Operand::Accumulator));
+    assert_eq!(cpu.decode(0x800A), (InstructionOperation::Nop, Operand::None));
+    assert_eq!(cpu.decode(0x800B), (InstructionOperation::Isb, Operand::AbsoluteX(0x0000)));
+}
+
+#[test]
+fn adc_loop_matches_execute_opcode() {
+    // CLC; LDA #$00; loop: ADC #$01; ADC #$03; SBC #$02; JMP loop
+    let program = [0x18, 0xA9, 0x00, 0x69, 0x01, 0x69, 0x03, 0xE9, 0x02, 0x4C, 0x03, 0x80];
+    let cpu_fetched = run_program(&program, 2 + 4 * 100);
+
+    let mut cpu_executed = cpu(bus());
+    cpu_executed.execute_opcode(0x18, &[]).unwrap();
+    cpu_executed.execute_opcode(0xA9, &[0x00]).unwrap();
+    for _ in 0..100 {
+        cpu_executed.execute_opcode(0x69, &[0x01]).unwrap();
+        cpu_executed.execute_opcode(0x69, &[0x03]).unwrap();
+        cpu_executed.execute_opcode(0xE9, &[0x02]).unwrap();
+    }
+
+    assert_eq!(cpu_fetched.registers.a, cpu_executed.registers.a);
+    assert_eq!(cpu_fetched.registers.p, cpu_executed.registers.p);
+    assert_eq!(cpu_fetched.registers.pc, 0x8003);
+    assert_eq!(cpu_fetched.cycle_count(), cpu_executed.cycle_count() + 3 * 100);
+}
+
+#[test]
+fn process_matches_execute_opcode_each_len() {
+    // INX; LDA #$42; STA $0200; LDX $0200; ASL A
+    let program: [&[u8]; 5] = [&[0xE8], &[0xA9, 0x42], &[0x8D, 0x00, 0x02], &[0xAE, 0x00, 0x02], &[0x0A]];
+
+    let mut bus_fetched = bus();
+    bus_fetched.write_n(ADDRESS_PRG, &program.concat()).unwrap();
+    let mut cpu_fetched = cpu(bus_fetched);
+    let mut cpu_executed = cpu(bus());
+
+    for bytes in program.iter() {
+        cpu_fetched.step().unwrap();
+        cpu_executed.execute_opcode(bytes[0], &bytes[1..]).unwrap();
+
+        assert_eq!(cpu_fetched.cycle_count(), cpu_executed.cycle_count());
+        assert_eq!(cpu_fetched.registers.a, cpu_executed.registers.a);
+        assert_eq!(cpu_fetched.registers.x, cpu_executed.registers.x);
+        assert_eq!(cpu_fetched.registers.p, cpu_executed.registers.p);
+    }
+
+    assert_eq!(cpu_fetched.registers.pc, ADDRESS_PRG + 10);
+    assert_eq!(cpu_fetched.bus.read(0x0200), 0x42);
+}
+
+#[test]
+fn decode_location() {
+    let location = InstructionInputLocation::Address(0x0200);
+    assert_eq!(location.as_address(), Some(0x0200));
+    assert!(!location.is_accumulator());
+
+    let location = InstructionInputLocation::Accumulator;
+    assert_eq!(location.as_address(), None);
+    assert!(location.is_accumulator());
+
+    let mut bus = bus();
+    // STA $0200,X; ASL A; LDA #$42; BNE -4
+    bus.write_n(ADDRESS_PRG, &[0x9D, 0x00, 0x02, 0x0A, 0xA9, 0x42, 0xD0, 0xFC]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.x = OFFSET_REGISTER_X;
+
+    assert_eq!(cpu.decode_location(0x8000), Some(InstructionInputLocation::Address(0x0200 + OFFSET_REGISTER_X as u16)));
+    assert_eq!(cpu.decode_location(0x8003), Some(InstructionInputLocation::Accumulator));
+    assert_eq!(cpu.decode_location(0x8004), None);
+    assert_eq!(cpu.decode_location(0x8006), None);
+}
+
+#[test]
+fn validate_against() {
+    let program = || {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, &[0xA9, 0x42, 0xAA, 0xE8]).unwrap();
+        cpu(bus)
+    };
+    let mut log: Vec<TraceLine> = [
+        "8000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:00 SP:FF PPU:  0,  0 CYC:0",
+        "8002  AA        TAX                             A:42 X:00 Y:00 P:00 SP:FF PPU:  0,  6 CYC:2",
+        "8003  E8        INX                             A:42 X:42 Y:00 P:00 SP:FF PPU:  0, 12 CYC:4",
+    ].iter().map(|line| TraceLine::parse(line).unwrap()).collect();
+
+    assert_eq!(program().validate_against(&log).unwrap(), None);
+    assert_eq!(TraceLine::parse(&program().trace_line()).unwrap(), log[0]);
+
+    log[2].x = 0x41;
+    let divergence = program().validate_against(&log).unwrap().unwrap();
+    assert_eq!(divergence.index, 2);
+    assert_eq!(divergence.field, TraceField::X);
+    assert_eq!(divergence.actual.x, 0x42);
+    assert_eq!(divergence.to_string(), "line `3` at `$8003`: expected `X` to be `41`, received `42`");
+
+    assert!(TraceLine::parse("8000  A9 42     LDA #$42").is_err());
+}
+
+#[test]
+fn diff_runner_variant_divergence() {
+    // SED, CLC, LDA #$09, ADC #$01, STA $00: only the MOS 6502 honours decimal mode
+    let program = |variant: CpuVariant| {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, &[0xF8, 0x18, 0xA9, 0x09, 0x69, 0x01, 0x85, 0x00]).unwrap();
+        let mut cpu = cpu(bus);
+        cpu.options_mut().set_variant(variant);
+        cpu
+    };
+
+    let mut runner = DiffRunner::new(program(CpuVariant::Ricoh2A03), program(CpuVariant::Ricoh2A03)).unwrap();
+    assert_eq!(runner.run(5).unwrap(), None);
+    assert_eq!(runner.left().registers.pc, 0x8008);
+
+    let mut runner = DiffRunner::new(program(CpuVariant::Ricoh2A03), program(CpuVariant::Mos6502)).unwrap();
+    let divergence = runner.run(5).unwrap().unwrap();
+    assert_eq!(divergence.instructions, 4);
+    assert_eq!(divergence.left.pc, 0x8006);
+    assert_eq!((divergence.left.a, divergence.right.a), (0x0A, 0x10));
+}
+
+#[test]
+fn cycles_until_next_scheduled_interrupt() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xEA, 0xEA, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), None);
+
+    cpu.schedule_nmi(5);
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(5));
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(3));
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), Some(1));
+
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycles_until_next_scheduled_interrupt(), None);
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+}
+
+#[test]
+fn set_overflow_pin() {
+    let mut cpu = cpu(bus());
+    cpu.registers.p = StatusFlags::CARRY;
+    let pc_old = cpu.registers.pc;
+
+    cpu.set_overflow_pin();
+    assert_eq!(cpu.registers.p, StatusFlags::CARRY | StatusFlags::OVERFLOW);
+    assert_eq!(cpu.registers.pc, pc_old);
+    assert_eq!(cpu.cycle_count(), 0);
+
+    // BVS is taken
+    process_instruction(&mut cpu, &[0x70, 0x10]);
+    assert_eq!(cpu.registers.pc, pc_old + 0x10 + 2);
+}
+
+#[test]
+fn cycle_timer() {
+    // LDA $4020; LDX $4021; LDY $4022
+    let program = [0xAD, 0x20, 0x40, 0xAE, 0x21, 0x40, 0xAC, 0x22, 0x40];
+    let mut cpu = run_program(&program, 0);
+    cpu.options_mut().set_cycle_timer(true);
+    cpu.clock.restore(0x0001_02F0);
+
+    cpu.run_for(3).unwrap();
+    assert_eq!(cpu.cycle_count(), 0x0001_02F0 + 12);
+    assert_eq!(cpu.registers.a, 0xF4);
+    assert_eq!(cpu.registers.x, 0x02);
+    assert_eq!(cpu.registers.y, 0x01);
+
+    let cpu = run_program(&program, 1);
+    assert_eq!(cpu.registers.a, 0x00);
+}
+
+#[test]
+fn expansion_open_bus() {
+    // LDA $5000, the high byte of the operand is the last value on the bus
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xAD, 0x00, 0x50]).unwrap();
+    bus.set_expansion_open_bus(true);
+    let mut cpu = cpu(bus);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.a, 0x50);
+}
+
+#[test]
+fn ppu_stub_vblank_wait() {
+    let mut bus = bus();
+    bus.register_handler(ppu::ADDRESS_RANGE, Rc::new(RefCell::new(PpuStub::new(100))));
+    // BIT $2002; BPL -5; INX
+    bus.write_n(ADDRESS_PRG, &[0x2C, 0x02, 0x20, 0x10, 0xFB, 0xE8]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.options_mut().set_instruction_limit(Some(100));
+
+    let summary = cpu.start();
+    assert_eq!(summary.stop_reason, StopReason::InstructionLimit);
+    assert_eq!(cpu.registers.x, 0x01);
+    assert!(summary.cycles >= 100);
+
+    cpu.bus.read(0x3FFA);
+    assert_eq!(cpu.bus.read(0x2002), 0x00);
+}
+
+#[test]
+fn interrupt_cycle_count() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xEA]).unwrap();
+    bus.write(ADDRESS_IRQ, 0x00);
+    let mut cpu = cpu(bus);
+
+    cpu.trigger_nmi();
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycle_count(), 2 + 7);
+    assert_eq!(cpu.interrupt_cycle_count(), 7);
+
+    cpu.registers.p.remove(StatusFlags::INTERRUPT_DISABLE);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.cycle_count(), 2 + 7 + 7);
+    assert_eq!(cpu.interrupt_cycle_count(), 7 + 7);
+
+    cpu.reset_cycles();
+    assert_eq!(cpu.interrupt_cycle_count(), 0);
+}
+
+#[test]
+fn nmi_line_edge_latched() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xAD, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]).unwrap();
+    bus.write_n(ADDRESS_IRQ, &[0xEA, 0xEA, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+
+    // pulsed while LDA is still on its second cycle
+    assert!(!cpu.tick().unwrap());
+    cpu.set_nmi_line(true);
+    cpu.set_nmi_line(false);
+    assert!(!cpu.tick().unwrap());
+    assert!(!cpu.tick().unwrap());
+    assert!(cpu.tick().unwrap());
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 3);
+
+    assert!(!cpu.tick().unwrap());
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+    assert_eq!(cpu.interrupt_cycle_count(), 7);
+    while !cpu.tick().unwrap() {}
+
+    // held asserted across several boundaries, only the edge counts
+    cpu.set_nmi_line(true);
+    cpu.run_for(3).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ + 2);
+    assert_eq!(cpu.interrupt_cycle_count(), 7 + 7);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ + 3);
+    assert_eq!(cpu.interrupt_cycle_count(), 7 + 7);
+}
+
+#[test]
+fn irq_line_level_triggered() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xEA, 0xEA]).unwrap();
+    // RTI
+    bus.write(ADDRESS_IRQ, 0x40);
+    let mut cpu = cpu(bus);
+
+    cpu.set_irq_line(true);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+    assert_eq!(cpu.interrupt_cycle_count(), 7);
+
+    // RTI clears I and the still-asserted line is serviced straight away
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+    assert_eq!(cpu.interrupt_cycle_count(), 7 + 7);
+
+    cpu.set_irq_line(false);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+    assert_eq!(cpu.interrupt_cycle_count(), 7 + 7);
+}
+
+#[test]
+fn irq_serviced_after_instruction() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    bus.write(INPUT_ADDRESS, 0x42);
+    bus.write_n(ADDRESS_PRG, &[0xAD, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH, 0xEA]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.p = StatusFlags::CARRY;
+
+    assert!(!cpu.tick().unwrap());
+    cpu.trigger_irq();
+    assert!(!cpu.tick().unwrap());
+    assert!(!cpu.tick().unwrap());
+    assert!(cpu.tick().unwrap());
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 3);
+
+    for _ in 0..6 {
+        assert!(!cpu.tick().unwrap());
+    }
+    assert!(cpu.tick().unwrap());
+    assert_eq!(cpu.registers.pc, ADDRESS_IRQ);
+    assert!(cpu.registers.p.contains(StatusFlags::INTERRUPT_DISABLE));
+    assert_eq!(cpu.cycle_count(), 4 + 7);
+
+    assert_eq!(cpu.stack_pull(), (StatusFlags::CARRY | StatusFlags::BREAK_LEFT).bits());
+    assert_eq!(cpu.stack_pull_u16(), ADDRESS_PRG + 3);
+}
+
+#[test]
+fn interrupt_polled_at_end_of_step() {
+    let mut bus = bus();
+    bus.write_u16(ADDRESS_VECTOR_NMI, 0x9000).unwrap();
+    bus.write_u16(ADDRESS_VECTOR_IRQ, ADDRESS_IRQ).unwrap();
+    bus.write_n(ADDRESS_PRG, &[0xE8, 0xE8]).unwrap();
+    let mut cpu = cpu(bus);
+    cpu.registers.p = StatusFlags::INTERRUPT_DISABLE;
+
+    cpu.trigger_irq();
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.x, 0x01);
+    assert_eq!(cpu.registers.pc, ADDRESS_PRG + 1);
+
+    cpu.trigger_nmi();
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers.x, 0x02);
+    assert_eq!(cpu.registers.pc, 0x9000);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn bus_access_counts() {
+    // LDA $0200, STA $0201, INX, INC $0202
+    let mut bus = bus();
+    bus.write_n(ADDRESS_PRG, &[0xAD, 0x00, 0x02, 0x8D, 0x01, 0x02, 0xE8, 0xEE, 0x02, 0x02]).unwrap();
+    let mut cpu = cpu(bus);
+    let (reads, writes) = (cpu.bus.read_count(), cpu.bus.write_count());
+
+    for _ in 0..4 {
+        cpu.run_instruction().unwrap().unwrap();
+    }
+
+    // 3 bytes fetched per absolute instruction, 1 for INX, plus LDA's and INC's operand reads
+    assert_eq!(cpu.bus.read_count() - reads, 3 + 3 + 1 + 3 + 2);
+    assert_eq!(cpu.bus.write_count() - writes, 2);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_anc_immediate() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0xFF);
+    process_instruction(&mut cpu, &[0x0B, 0x80]);
+    assert_eq!(cpu.registers.a, 0x80);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE | StatusFlags::CARRY);
+
+    process_instruction(&mut cpu, &[0x2B, 0x00]);
+    assert_eq!(cpu.registers.a, 0x00);
+    assert_eq!(cpu.registers.p, StatusFlags::ZERO);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_alr_immediate() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0xFF);
+    process_instruction(&mut cpu, &[0x4B, 0x83]);
+    assert_eq!(cpu.registers.a, 0x41);
+    assert_eq!(cpu.registers.p, StatusFlags::CARRY);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_arr_immediate() {
+    let mut cpu = cpu(bus());
+    lda_no_flags(&mut cpu, 0xFF);
+    cpu.registers.p = StatusFlags::CARRY;
+    process_instruction(&mut cpu, &[0x6B, 0xFF]);
+    assert_eq!(cpu.registers.a, 0xFF);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE | StatusFlags::CARRY);
+
+    lda_no_flags(&mut cpu, 0xFF);
+    process_instruction(&mut cpu, &[0x6B, 0x80]);
+    assert_eq!(cpu.registers.a, 0x40);
+    assert_eq!(cpu.registers.p, StatusFlags::OVERFLOW | StatusFlags::CARRY);
+
+    lda_no_flags(&mut cpu, 0xFF);
+    process_instruction(&mut cpu, &[0x6B, 0x40]);
+    assert_eq!(cpu.registers.a, 0x20);
+    assert_eq!(cpu.registers.p, StatusFlags::OVERFLOW);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_slo_zero_page() {
+    let mut cpu = cpu(bus());
+    cpu.bus.write(INPUT_ADDRESS_ZP, 0x81);
+    lda_no_flags(&mut cpu, 0x10);
+    process_instruction(&mut cpu, &[0x07, INPUT_ADDRESS_ZP as u8]);
+    assert_eq!(cpu.bus.read(INPUT_ADDRESS_ZP), 0x02);
+    assert_eq!(cpu.registers.a, 0x12);
+    assert_eq!(cpu.registers.p, StatusFlags::CARRY);
+
+    cpu.bus.write(INPUT_ADDRESS, 0x40);
+    process_instruction(&mut cpu, &[0x0F, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]);
+    assert_eq!(cpu.bus.read(INPUT_ADDRESS), 0x80);
+    assert_eq!(cpu.registers.a, 0x92);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_rla_absolute_x() {
+    let mut cpu = cpu(bus());
+    cpu.registers.x = OFFSET_REGISTER_X;
+    cpu.bus.write(INPUT_ADDRESS + OFFSET_REGISTER_X as u16, 0x80);
+    lda_no_flags(&mut cpu, 0xFF);
+    cpu.registers.p = StatusFlags::CARRY;
+    process_instruction(&mut cpu, &[0x3F, INPUT_ADDRESS_LOW, INPUT_ADDRESS_HIGH]);
+    assert_eq!(cpu.bus.read(INPUT_ADDRESS + OFFSET_REGISTER_X as u16), 0x01);
+    assert_eq!(cpu.registers.a, 0x01);
+    assert_eq!(cpu.registers.p, StatusFlags::CARRY);
+
+    cpu.bus.write(INPUT_ADDRESS_ZP, 0x00);
+    cpu.registers.p = StatusFlags::empty();
+    process_instruction(&mut cpu, &[0x27, INPUT_ADDRESS_ZP as u8]);
+    assert_eq!(cpu.registers.a, 0x00);
+    assert_eq!(cpu.registers.p, StatusFlags::ZERO);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_sre_indirect_y() {
+    let mut bus = bus();
+    bus.write_u16(INPUT_ADDRESS_ZP, INPUT_ADDRESS).unwrap();
+    bus.write(INPUT_ADDRESS + OFFSET_REGISTER_Y as u16, 0x03);
+    let mut cpu = cpu(bus);
+    cpu.registers.y = OFFSET_REGISTER_Y;
+    lda_no_flags(&mut cpu, 0x81);
+
+    process_instruction(&mut cpu, &[0x53, INPUT_ADDRESS_ZP as u8]);
+    assert_eq!(cpu.bus.read(INPUT_ADDRESS + OFFSET_REGISTER_Y as u16), 0x01);
+    assert_eq!(cpu.registers.a, 0x80);
+    assert_eq!(cpu.registers.p, StatusFlags::NEGATIVE | StatusFlags::CARRY);
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn process_rra_zero_page_x() {
+    let mut cpu = cpu(bus());
+    cpu.registers.x = OFFSET_REGISTER_X;
+    let address = INPUT_ADDRESS_ZP + OFFSET_REGISTER_X as u16;
+    cpu.bus.write(address, 0x03);
+    lda_no_flags(&mut cpu, 0x10);
+
+    // the carry out of the ROR feeds the ADC
+    process_instruction(&mut cpu, &[0x77, INPUT_ADDRESS_ZP as u8]);
+    assert_eq!(cpu.bus.read(address), 0x01);
+    assert_eq!(cpu.registers.a, 0x12);
+    assert_eq!(cpu.registers.p, StatusFlags::empty());
+
+    cpu.bus.write(address, 0x02);
+    process_instruction(&mut cpu, &[0x77, INPUT_ADDRESS_ZP as u8]);
+    assert_eq!(cpu.bus.read(address), 0x01);
+    assert_eq!(cpu.registers.a, 0x13);
+    assert_eq!(cpu.registers.p, StatusFlags::empty());
+}
+
+#[cfg(feature = "illegal-opcodes")]
+#[test]
+fn rmw_combos_single_access() {
+    let address = INPUT_ADDRESS_ZP;
+
+    // SLO, RLA, SRE and RRA on the zero page
+    for opcode in &[0x07, 0x27, 0x47, 0x67] {
+        let mut bus = bus();
+        bus.write_n(ADDRESS_PRG, &[*opcode, address as u8]).unwrap();
+        bus.write(address, 0x81);
+        let mut cpu = cpu(bus);
+        cpu.bus.set_access_log_capacity(8);
+
+        cpu.run_instruction().unwrap().unwrap();
+        let accesses = cpu.bus.access_log()
+            .into_iter()
+            .filter(|access| access.address == address)
+            .map(|access| access.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(accesses, vec![AccessKind::Read, AccessKind::Write], "opcode `${:02X}`", opcode);
+        assert_eq!(cpu.bus.access_log().len(), 4, "opcode `${:02X}`", opcode);
+    }
+}
+
+#[cfg(feature = "logging")]
+thread_local! {
+    static LOG_RECORDS: std::cell::RefCell<Vec<(log::Level, String)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(feature = "logging")]
+struct CapturingLogger;
+
+#[cfg(feature = "logging")]
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOG_RECORDS.with(|records| records.borrow_mut().push((record.level(), record.args().to_string())));
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(feature = "logging")]
+fn capture_logs() {
+    static LOGGER: CapturingLogger = CapturingLogger;
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+}
+
+#[cfg(feature = "logging")]
+fn warned(text: &str) -> bool {
+    LOG_RECORDS.with(|records| {
+        records.borrow().iter().any(|(level, message)| *level == log::Level::Warn && message.contains(text))
+    })
+}
+
+#[cfg(feature = "logging")]
+#[test]
+fn stack_underflow_warns() {
+    capture_logs();
+
+    let mut cpu = cpu(bus());
+    process_instruction(&mut cpu, &[0x68]);
+    assert!(warned("stack underflow"));
+}
+
+#[cfg(feature = "logging")]
+#[test]
+fn reset_vector_unmapped_warns() {
+    capture_logs();
+
+    let mut bus = Bus::new();
+    bus.insert_cartridge(Cartridge::from_bytes(&image(1, 0, 0, 0)).unwrap());
+    Cpu::new(bus).unwrap();
+    assert!(warned("reset vector $0000 is outside cartridge ROM"));
+}
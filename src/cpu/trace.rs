@@ -0,0 +1,135 @@
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use super::Cpu;
+use crate::types::Result;
+
+// Streams a trace line per instruction, buffered so a long run does not cost a syscall per line
+pub(super) struct TraceWriter {
+    out: BufWriter<Box<dyn Write>>,
+    // reused across instructions
+    line: String,
+}
+
+impl TraceWriter {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self { out: BufWriter::new(out), line: String::new() }
+    }
+
+    pub fn write(&mut self, cpu: &Cpu) -> Result {
+        self.line.clear();
+        cpu.write_trace(&mut self.line).expect("writing to a String cannot fail");
+        writeln!(self.out, "{}", self.line)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// The machine state of one nestest.log line, the disassembly and PPU columns are not compared
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceLine {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+impl TraceLine {
+    pub fn parse(line: &str) -> Result<Self> {
+        let pc = line.get(0..4).ok_or_else(|| anyhow!("missing PC in `{}`", line))?;
+        let pc = u16::from_str_radix(pc, 16)?;
+
+        let bytes = line.get(6..15).ok_or_else(|| anyhow!("missing instruction bytes in `{}`", line))?;
+        let bytes = bytes
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let field = |name: &str| -> Result<&str> {
+            line.split_whitespace()
+                .find_map(|token| token.strip_prefix(name))
+                .ok_or_else(|| anyhow!("missing `{}` in `{}`", name, line))
+        };
+
+        Ok(Self {
+            pc,
+            bytes,
+            a: u8::from_str_radix(field("A:")?, 16)?,
+            x: u8::from_str_radix(field("X:")?, 16)?,
+            y: u8::from_str_radix(field("Y:")?, 16)?,
+            p: u8::from_str_radix(field("P:")?, 16)?,
+            sp: u8::from_str_radix(field("SP:")?, 16)?,
+            cycles: field("CYC:")?.parse()?,
+        })
+    }
+
+    pub fn first_mismatch(&self, actual: &TraceLine) -> Option<TraceField> {
+        if self.pc != actual.pc {
+            Some(TraceField::Pc)
+        } else if self.bytes != actual.bytes {
+            Some(TraceField::Bytes)
+        } else if self.a != actual.a {
+            Some(TraceField::A)
+        } else if self.x != actual.x {
+            Some(TraceField::X)
+        } else if self.y != actual.y {
+            Some(TraceField::Y)
+        } else if self.p != actual.p {
+            Some(TraceField::P)
+        } else if self.sp != actual.sp {
+            Some(TraceField::Sp)
+        } else if self.cycles != actual.cycles {
+            Some(TraceField::Cycles)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceField {
+    Pc,
+    Bytes,
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Cycles,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceDivergence {
+    // index into the golden log
+    pub index: usize,
+    pub field: TraceField,
+    pub expected: TraceLine,
+    pub actual: TraceLine,
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (expected, actual) = match self.field {
+            TraceField::Pc => (format!("{:04X}", self.expected.pc), format!("{:04X}", self.actual.pc)),
+            TraceField::Bytes => (format!("{:02X?}", self.expected.bytes), format!("{:02X?}", self.actual.bytes)),
+            TraceField::A => (format!("{:02X}", self.expected.a), format!("{:02X}", self.actual.a)),
+            TraceField::X => (format!("{:02X}", self.expected.x), format!("{:02X}", self.actual.x)),
+            TraceField::Y => (format!("{:02X}", self.expected.y), format!("{:02X}", self.actual.y)),
+            TraceField::P => (format!("{:02X}", self.expected.p), format!("{:02X}", self.actual.p)),
+            TraceField::Sp => (format!("{:02X}", self.expected.sp), format!("{:02X}", self.actual.sp)),
+            TraceField::Cycles => (self.expected.cycles.to_string(), self.actual.cycles.to_string()),
+        };
+
+        write!(
+            f,
+            "line `{}` at `${:04X}`: expected `{:?}` to be `{}`, received `{}`",
+            self.index + 1, self.expected.pc, self.field, expected, actual,
+        )
+    }
+}
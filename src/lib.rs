@@ -4,18 +4,34 @@ extern crate anyhow;
 extern crate bitflags;
 #[macro_use]
 extern crate getset;
+#[cfg(feature = "logging")]
+#[macro_use]
+extern crate log;
 
+#[cfg(not(feature = "logging"))]
+#[macro_use]
+mod logging;
 mod types;
+mod apu;
 mod bus;
+mod cartridge;
 mod cpu;
+mod ppu;
+mod program;
 mod ui;
 
-use types::Result;
-use bus::Bus;
-use cpu::Cpu;
+pub use types::Result;
+pub use apu::Apu;
+pub use bus::{Access, AccessKind, Bus, Controller, JoypadButton, Memory, MmioHandler, SharedMmioHandler, Ram, ClosureMemory};
+pub use cartridge::{Cartridge, CartridgeInfo, Mapper, Mirroring, Nrom};
+pub use cpu::{Cpu, CpuError, CpuEvent, CpuOptions, CpuStatus, CpuVariant, RunSummary, StopReason, SaveState, StatusFlags, FlagStates, TraceLine, TraceField, TraceDivergence, DiffRunner, StateDivergence, StepResult, ResolvedOperand, Instruction, InstructionOperation, OperandBytes, decode_stream, InstructionMode, InstructionInputLocation, Operand};
+pub use ppu::PpuStub;
+pub use program::TestProgram;
+
 use ui::RuntimeUi;
 use tui::backend::CrosstermBackend;
 use std::io;
+use std::rc::Rc;
 
 pub fn run() -> Result {
     let mut _ui = {
@@ -27,7 +43,8 @@ pub fn run() -> Result {
 
     let bus = Bus::new();
     let mut cpu = Cpu::new(bus)?;
-    cpu.start()?;
-
-    Ok(())
+    match cpu.start().stop_reason {
+        StopReason::Error(error) => Err(Rc::try_unwrap(error).unwrap_or_else(|error| anyhow!("{:#}", error))),
+        _ => Ok(()),
+    }
 }
@@ -0,0 +1,26 @@
+// Stand-ins for the `log` macros when the `logging` feature is disabled. The arguments are still
+// type checked but never evaluated.
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
@@ -0,0 +1,54 @@
+use std::ops::RangeInclusive;
+
+use crate::bus::MmioHandler;
+
+pub const ADDRESS_RANGE: RangeInclusive<u16> = 0x2000..=0x3FFF;
+const REGISTER_STATUS: u16 = 0x2002;
+// PPUCTRL, PPUMASK, OAMADDR, PPUSCROLL and PPUADDR
+const REGISTERS_WRITE_ONLY: [u16; 5] = [0x2000, 0x2001, 0x2003, 0x2005, 0x2006];
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+// Not a PPU: it only raises the vblank bit of $2002 every `cadence` cycles so polling loops can make progress.
+// As on hardware, reading $2002 clears the bit again.
+pub struct PpuStub {
+    cadence: u32,
+    cycles: u32,
+    vblank: bool,
+}
+
+impl PpuStub {
+    pub fn new(cadence: u32) -> Self {
+        Self { cadence: cadence.max(1), cycles: 0, vblank: false }
+    }
+}
+
+impl MmioHandler for PpuStub {
+    fn write(&mut self, _address: u16, _value: u8) {}
+
+    fn read(&mut self, address: u16) -> Option<u8> {
+        let status = self.peek(address)?;
+        self.vblank = false;
+        Some(status)
+    }
+
+    fn peek(&self, address: u16) -> Option<u8> {
+        // the eight registers repeat across the whole range
+        if !ADDRESS_RANGE.contains(&address) || address & 0x2007 != REGISTER_STATUS {
+            return None;
+        }
+
+        Some(if self.vblank { STATUS_VBLANK } else { 0 })
+    }
+
+    fn is_write_only(&self, address: u16) -> bool {
+        ADDRESS_RANGE.contains(&address) && REGISTERS_WRITE_ONLY.contains(&(address & 0x2007))
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as u32;
+        if self.cycles >= self.cadence {
+            self.cycles %= self.cadence;
+            self.vblank = true;
+        }
+    }
+}
@@ -0,0 +1,29 @@
+mod tests;
+
+// Ready-to-load byte patterns for tests and fuzzing, none of them depend on where they are loaded
+pub struct TestProgram;
+
+impl TestProgram {
+    pub fn nop_sled(len: usize) -> Vec<u8> {
+        vec![0xEA; len]
+    }
+
+    // CLV; BVC -2, spinning on the branch
+    pub fn infinite_loop() -> Vec<u8> {
+        vec![0xB8, 0x50, 0xFE]
+    }
+
+    // sums `count` down to 1 into A, then halts on a JAM; a count of zero runs 256 times
+    pub fn adc_loop(count: u8) -> Vec<u8> {
+        vec![
+            0xA9, 0x00,  // LDA #$00
+            0xA2, count, // LDX #count
+            0x86, 0x00,  // STX $00
+            0x18,        // CLC
+            0x65, 0x00,  // ADC $00
+            0xCA,        // DEX
+            0xD0, 0xF8,  // BNE -8
+            0x02,        // JAM
+        ]
+    }
+}
@@ -0,0 +1,40 @@
+#![cfg(test)]
+
+use super::*;
+use crate::{Bus, Cpu, CpuStatus, StopReason};
+
+fn load(program: &[u8]) -> Cpu {
+    let mut bus = Bus::new();
+    bus.write_u16(0xFFFC, 0x8000).unwrap();
+    bus.write_n(0x8000, program).unwrap();
+    Cpu::new(bus).unwrap()
+}
+
+#[test]
+fn nop_sled() {
+    let mut cpu = load(&TestProgram::nop_sled(16));
+    assert_eq!(cpu.run_for(16).unwrap(), 16);
+    assert_eq!(cpu.trace_state().pc, 0x8010);
+    assert_eq!(cpu.cycle_count(), 32);
+}
+
+#[test]
+fn infinite_loop() {
+    let mut cpu = load(&TestProgram::infinite_loop());
+    cpu.options_mut().set_instruction_limit(Some(100));
+    assert_eq!(cpu.start().stop_reason, StopReason::InstructionLimit);
+    assert_eq!(cpu.trace_state().pc, 0x8001);
+}
+
+#[test]
+fn adc_loop() {
+    let mut cpu = load(&TestProgram::adc_loop(10));
+    assert_eq!(cpu.start().stop_reason, StopReason::Halted);
+    assert_eq!(cpu.status(), CpuStatus::Halted);
+    assert_eq!(cpu.trace_state().a, 55);
+    assert_eq!(cpu.trace_state().x, 0);
+
+    let mut cpu = load(&TestProgram::adc_loop(0));
+    cpu.start();
+    assert_eq!(cpu.trace_state().a, (0..=255u32).sum::<u32>() as u8);
+}